@@ -0,0 +1,667 @@
+//! Level statistics and the `audio_gain` recommendation.
+//!
+//! `AudioAnalyzer` is fed a track's samples after the gain, ahead of the
+//! limiter, as they are produced, so a recording's statistics are ready the
+//! moment it stops; `analyze` does the same for a whole buffer at once.  The result is
+//! an `AudioAnalysis` that can be logged, written as the `_audio.json`
+//! sidecar, or used for its recommended gain.  `quality_report` adds the
+//! extras `pstt analyze` shows for an arbitrary WAV file: windowed stats, an
+//! SNR estimate and a DC offset check.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::vad::{EnergyDetector, SpeechDetector};
+
+/// RMS level the gain recommendation (and automatic gain control) aims for.
+pub const TARGET_RMS: f32 = 0.08;
+/// Highest gain the recommendation (and automatic gain control) will use.
+pub const MAX_GAIN: f32 = 20.0;
+
+/// Ideally less than this share of the samples is very quiet.
+const TARGET_QUIET_PCT: f32 = 30.0;
+const MINUTE_SECS: u32 = 60;
+
+/// Share of samples per level band, in percent of all samples.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LevelDistribution {
+    /// |s| < 0.01
+    pub very_quiet_pct: f32,
+    /// 0.01 ≤ |s| < 0.1
+    pub normal_pct: f32,
+    /// 0.1 ≤ |s| < 0.5
+    pub loud_pct: f32,
+    /// 0.5 ≤ |s| < 0.99
+    pub very_loud_pct: f32,
+    /// |s| ≥ 0.99
+    pub clipped_pct: f32,
+}
+
+/// Level statistics over one stretch of audio.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AudioStats {
+    pub samples: u64,
+    pub duration_secs: f64,
+    pub rms: f32,
+    /// Largest absolute sample.
+    pub peak: f32,
+    pub min: f32,
+    pub max: f32,
+    pub clipped_pct: f32,
+    /// Same as `distribution.very_quiet_pct`.
+    pub quiet_pct: f32,
+    /// Mean sample value; should be close to zero.
+    pub dc_offset: f32,
+    pub distribution: LevelDistribution,
+}
+
+/// Statistics of one minute of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MinuteStats {
+    /// 0-based minute of the recording.
+    pub minute: usize,
+    #[serde(flatten)]
+    pub stats: AudioStats,
+}
+
+/// Full analysis of a recording made at `audio_gain`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AudioAnalysis {
+    pub sample_rate: u32,
+    pub audio_gain: f32,
+    #[serde(flatten)]
+    pub stats: AudioStats,
+    pub recommended_gain: f32,
+    /// What's wrong with the levels, if anything.
+    pub problem: Option<LevelProblem>,
+    pub minutes: Vec<MinuteStats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelProblem {
+    /// More than half of the audio is very quiet.
+    MostlyQuiet,
+    /// More than 5% of the samples are clipped.
+    Clipping,
+    /// The overall level is too low.
+    LowLevel,
+}
+
+/// Running sums for `AudioStats`.
+#[derive(Debug, Clone, Default)]
+struct LevelCounter {
+    count: u64,
+    sum: f64,
+    sum_squares: f64,
+    min: f32,
+    max: f32,
+    /// very quiet, normal, loud, very loud, clipped
+    bands: [u64; 5],
+}
+
+impl LevelCounter {
+    fn push(&mut self, sample: f32) {
+        let abs = sample.abs();
+        self.count += 1;
+        self.sum += sample as f64;
+        self.sum_squares += (abs as f64).powi(2);
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        let band = if abs < 0.01 {
+            0
+        } else if abs < 0.1 {
+            1
+        } else if abs < 0.5 {
+            2
+        } else if abs >= 0.99 {
+            4
+        } else {
+            3
+        };
+        self.bands[band] += 1;
+    }
+
+    fn stats(&self, sample_rate: u32) -> AudioStats {
+        if self.count == 0 {
+            return AudioStats::default();
+        }
+        let pct = |n: u64| n as f32 / self.count as f32 * 100.0;
+        let distribution = LevelDistribution {
+            very_quiet_pct: pct(self.bands[0]),
+            normal_pct: pct(self.bands[1]),
+            loud_pct: pct(self.bands[2]),
+            very_loud_pct: pct(self.bands[3]),
+            clipped_pct: pct(self.bands[4]),
+        };
+        AudioStats {
+            samples: self.count,
+            duration_secs: self.count as f64 / sample_rate as f64,
+            rms: (self.sum_squares / self.count as f64).sqrt() as f32,
+            peak: self.max.max(-self.min),
+            min: self.min,
+            max: self.max,
+            clipped_pct: distribution.clipped_pct,
+            quiet_pct: distribution.very_quiet_pct,
+            dc_offset: (self.sum / self.count as f64) as f32,
+            distribution,
+        }
+    }
+}
+
+/// Streaming analyzer: push samples as they are recorded, `finish` at the end.
+pub struct AudioAnalyzer {
+    sample_rate: u32,
+    minute_len: u64,
+    total: LevelCounter,
+    minute: LevelCounter,
+    minutes: Vec<MinuteStats>,
+}
+
+impl AudioAnalyzer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            minute_len: sample_rate as u64 * MINUTE_SECS as u64,
+            total: LevelCounter::default(),
+            minute: LevelCounter::default(),
+            minutes: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        for &s in samples {
+            self.total.push(s);
+            self.minute.push(s);
+            if self.minute.count == self.minute_len {
+                self.close_minute();
+            }
+        }
+    }
+
+    /// Statistics so far, with the gain recommendation for `audio_gain`.
+    pub fn finish(mut self, audio_gain: f32) -> AudioAnalysis {
+        if self.minute.count > 0 {
+            self.close_minute();
+        }
+        let stats = self.total.stats(self.sample_rate);
+        AudioAnalysis {
+            sample_rate: self.sample_rate,
+            audio_gain,
+            recommended_gain: recommend_gain(&stats, audio_gain),
+            problem: find_problem(&stats),
+            stats,
+            minutes: self.minutes,
+        }
+    }
+
+    fn close_minute(&mut self) {
+        let minute = std::mem::take(&mut self.minute);
+        self.minutes.push(MinuteStats {
+            minute: self.minutes.len(),
+            stats: minute.stats(self.sample_rate),
+        });
+    }
+}
+
+/// Analyze a whole recording made at `audio_gain`.
+pub fn analyze(samples: &[f32], sample_rate: u32, audio_gain: f32) -> AudioAnalysis {
+    let mut analyzer = AudioAnalyzer::new(sample_rate);
+    analyzer.push(samples);
+    analyzer.finish(audio_gain)
+}
+
+/// The `audio_gain` that would bring `stats` (recorded at `current_gain`)
+/// to a good level.
+pub fn recommend_gain(stats: &AudioStats, current_gain: f32) -> f32 {
+    if stats.samples == 0 {
+        return current_gain;
+    }
+    let quiet_pct = stats.quiet_pct;
+    if quiet_pct > 50.0 {
+        // Audio is mostly silence/noise - need MORE gain
+        // (sqrt so the correction is less aggressive)
+        let gain_multiplier = (quiet_pct / TARGET_QUIET_PCT).sqrt().min(3.0);
+        (current_gain * gain_multiplier).min(MAX_GAIN).max(current_gain * 1.5)
+    } else if stats.rms < TARGET_RMS {
+        // Audio is present but RMS too low - increase proportionally
+        (current_gain * TARGET_RMS / stats.rms.max(0.001)).min(MAX_GAIN)
+    } else if stats.clipped_pct > 1.0 {
+        // Too much clipping - decrease gain
+        let clip_reduction = 1.0 - (stats.clipped_pct / 100.0).min(0.5);
+        (current_gain * clip_reduction).max(1.0)
+    } else {
+        current_gain
+    }
+}
+
+fn find_problem(stats: &AudioStats) -> Option<LevelProblem> {
+    if stats.samples == 0 {
+        None
+    } else if stats.quiet_pct > 50.0 {
+        Some(LevelProblem::MostlyQuiet)
+    } else if stats.clipped_pct > 5.0 {
+        Some(LevelProblem::Clipping)
+    } else if stats.rms < 0.05 {
+        Some(LevelProblem::LowLevel)
+    } else {
+        None
+    }
+}
+
+impl AudioAnalysis {
+    /// Write the analysis as pretty-printed JSON.
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Log the statistics and the recommendation.
+    pub fn log(&self) {
+        let stats = &self.stats;
+        let dist = &stats.distribution;
+        log::info!("=== AUDIO ANALYSIS ===");
+        log::info!("Samples: {}", stats.samples);
+        log::info!("Duration: {:.1} seconds ({:.1} minutes)", stats.duration_secs, stats.duration_secs / 60.0);
+        log::info!("=====================");
+        log::info!("Audio Levels:");
+        log::info!("  Average (RMS): {:.4}", stats.rms);
+        log::info!("  Peak (max):    {:.4}", stats.max);
+        log::info!("  Minimum (min): {:.4}", stats.min);
+        log::info!("");
+        log::info!("Distribution:");
+        log::info!("  Very quiet (< 0.01): {:.1}%", dist.very_quiet_pct);
+        log::info!("  Normal (0.01-0.1):   {:.1}%", dist.normal_pct);
+        log::info!("  Loud (> 0.1):        {:.1}%", dist.loud_pct);
+        log::info!("  Very loud (> 0.5):   {:.1}%", dist.very_loud_pct);
+        log::info!("  Clipped (≈ 1.0):     {:.2}%", dist.clipped_pct);
+        log::info!("=====================");
+        log::info!("");
+
+        let Some(problem) = self.problem else {
+            log::info!("✅ Audio levels look good!");
+            log::info!("   Current gain ({:.1}) is appropriate.", self.audio_gain);
+            log::info!("");
+            return;
+        };
+        match problem {
+            LevelProblem::MostlyQuiet => {
+                log::warn!("❌ PROBLEM: {:.0}% of audio is very quiet!", stats.quiet_pct);
+                log::warn!("   This will cause poor transcription quality.");
+            }
+            LevelProblem::Clipping => {
+                log::warn!("❌ PROBLEM: {:.1}% of audio is clipped!", stats.clipped_pct);
+                log::warn!("   This causes distortion and poor quality.");
+            }
+            LevelProblem::LowLevel => {
+                log::warn!("❌ PROBLEM: Overall audio level too low (RMS: {:.4})", stats.rms);
+                log::warn!("   This will cause poor transcription quality.");
+            }
+        }
+        log::warn!("");
+        if self.recommended_gain != self.audio_gain {
+            let direction = if self.recommended_gain > self.audio_gain { "Increase" } else { "Decrease" };
+            log::warn!("   SOLUTION: {} audio_gain in config.toml", direction);
+            log::warn!("   Current: audio_gain = {:.1}", self.audio_gain);
+            log::warn!("   Recommended: audio_gain = {:.1}", self.recommended_gain);
+        } else {
+            log::warn!("   Note: Current gain seems appropriate.");
+            log::warn!("   Issue may be with microphone input level.");
+        }
+        log::warn!("");
+    }
+}
+
+// ── WAV quality report (`pstt analyze`) ─────────────────────────────────────
+
+/// Frame length for telling speech from silence in the SNR estimate.
+const SNR_FRAME_MS: u32 = 20;
+/// DC offsets larger than this (about -40 dBFS) are reported.
+pub const DC_OFFSET_LIMIT: f32 = 0.01;
+
+/// A WAV file mixed down to mono.
+pub struct WavAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Read any integer or float WAV, averaging its channels.
+pub fn load_wav(path: &Path) -> Result<WavAudio> {
+    let mut reader = hound::WavReader::open(path)
+        .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample.max(1) - 1)) as f32;
+            reader.samples::<i32>().map(|s| s.map(|s| s as f32 * scale)).collect::<Result<_, _>>()?
+        }
+    };
+    let channels = spec.channels.max(1) as usize;
+    let samples = interleaved
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok(WavAudio { samples, sample_rate: spec.sample_rate, channels: spec.channels })
+}
+
+/// Statistics of one window of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WindowStats {
+    pub start_secs: f64,
+    #[serde(flatten)]
+    pub stats: AudioStats,
+}
+
+/// Cut `samples` into windows of `window_len` samples (the last may be shorter).
+pub fn window_stats(samples: &[f32], sample_rate: u32, window_len: usize) -> Vec<WindowStats> {
+    samples
+        .chunks(window_len.max(1))
+        .enumerate()
+        .map(|(i, window)| {
+            let mut counter = LevelCounter::default();
+            window.iter().for_each(|&s| counter.push(s));
+            WindowStats {
+                start_secs: (i * window_len) as f64 / sample_rate as f64,
+                stats: counter.stats(sample_rate),
+            }
+        })
+        .collect()
+}
+
+/// Signal-to-noise ratio of speech against the pauses between it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnrEstimate {
+    /// `None` when there are no voiced or no (non-digital) silent frames.
+    pub snr_db: Option<f32>,
+    /// Share of frames classified as speech.
+    pub speech_pct: f32,
+    /// Noise level in the silent frames (dBFS).
+    pub noise_floor_db: Option<f32>,
+}
+
+/// Classify frames with the VAD's energy detector and compare the power of
+/// voiced frames (minus the noise in them) with that of silent frames.
+pub fn estimate_snr(samples: &[f32], sample_rate: u32, threshold_db: f32) -> SnrEstimate {
+    let frame_len = (sample_rate * SNR_FRAME_MS / 1000).max(1) as usize;
+    let mut detector = EnergyDetector::new(threshold_db);
+    let (mut speech, mut silence) = ((0.0f64, 0usize), (0.0f64, 0usize));
+    for frame in samples.chunks_exact(frame_len) {
+        let power = frame.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / frame_len as f64;
+        let bucket = if detector.is_speech(frame) { &mut speech } else { &mut silence };
+        bucket.0 += power;
+        bucket.1 += 1;
+    }
+    let frames = speech.1 + silence.1;
+    let noise = (silence.1 > 0).then(|| silence.0 / silence.1 as f64).filter(|&p| p > 0.0);
+    let snr_db = match (noise, speech.1) {
+        (Some(noise), n) if n > 0 => {
+            let signal = (speech.0 / n as f64 - noise).max(noise * 1e-3);
+            Some((10.0 * (signal / noise).log10()) as f32)
+        }
+        _ => None,
+    };
+    SnrEstimate {
+        snr_db,
+        speech_pct: if frames == 0 { 0.0 } else { speech.1 as f32 / frames as f32 * 100.0 },
+        noise_floor_db: noise.map(|p| (10.0 * p.log10()) as f32),
+    }
+}
+
+/// Everything `pstt analyze` reports about a WAV file.
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityReport {
+    pub file: String,
+    pub channels: u16,
+    #[serde(flatten)]
+    pub analysis: AudioAnalysis,
+    pub dc_offset_ok: bool,
+    pub snr: SnrEstimate,
+    /// Window length of `windows`, when windowed stats were asked for.
+    pub window_secs: Option<f64>,
+    pub windows: Vec<WindowStats>,
+}
+
+pub fn quality_report(
+    file: &str,
+    audio: &WavAudio,
+    audio_gain: f32,
+    window_secs: Option<f64>,
+    vad_threshold_db: f32,
+) -> QualityReport {
+    let analysis = analyze(&audio.samples, audio.sample_rate, audio_gain);
+    let windows = window_secs
+        .map(|secs| {
+            let len = (secs * audio.sample_rate as f64).round() as usize;
+            window_stats(&audio.samples, audio.sample_rate, len)
+        })
+        .unwrap_or_default();
+    QualityReport {
+        file: file.to_string(),
+        channels: audio.channels,
+        dc_offset_ok: analysis.stats.dc_offset.abs() <= DC_OFFSET_LIMIT,
+        snr: estimate_snr(&audio.samples, audio.sample_rate, vad_threshold_db),
+        analysis,
+        window_secs,
+        windows,
+    }
+}
+
+fn dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-6).log10()
+}
+
+impl QualityReport {
+    /// Human-readable report.
+    pub fn to_text(&self) -> String {
+        let a = &self.analysis;
+        let stats = &a.stats;
+        let dist = &stats.distribution;
+        let mut out = String::new();
+        let _ = writeln!(out, "📊 Audio report: {}", self.file);
+        let _ = writeln!(out, "   Format:     {} Hz, {} ch, {:.1} s", a.sample_rate, self.channels, stats.duration_secs);
+        let _ = writeln!(
+            out,
+            "   Level:      RMS {:.4} ({:.1} dBFS), peak {:.4} ({:.1} dBFS)",
+            stats.rms, dbfs(stats.rms), stats.peak, dbfs(stats.peak)
+        );
+        let _ = writeln!(out, "   Clipped:    {:.2}%    Very quiet: {:.1}%", stats.clipped_pct, stats.quiet_pct);
+        let _ = writeln!(
+            out,
+            "   Spread:     normal {:.1}%, loud {:.1}%, very loud {:.1}%",
+            dist.normal_pct, dist.loud_pct, dist.very_loud_pct
+        );
+        if self.dc_offset_ok {
+            let _ = writeln!(out, "   DC offset:  {:+.4} ✅", stats.dc_offset);
+        } else {
+            let _ = writeln!(
+                out,
+                "   DC offset:  {:+.4} ⚠️  add a dc_blocker [[dsp]] stage",
+                stats.dc_offset
+            );
+        }
+        match (self.snr.snr_db, self.snr.noise_floor_db) {
+            (Some(snr), Some(floor)) => {
+                let _ = writeln!(
+                    out,
+                    "   SNR:        {:.1} dB (noise floor {:.1} dBFS, speech in {:.0}% of frames)",
+                    snr, floor, self.snr.speech_pct
+                );
+            }
+            _ => {
+                let _ = writeln!(
+                    out,
+                    "   SNR:        n/a (needs both speech and pauses; speech in {:.0}% of frames)",
+                    self.snr.speech_pct
+                );
+            }
+        }
+        let verdict = match a.problem {
+            None => "✅ levels look good".to_string(),
+            Some(LevelProblem::MostlyQuiet) => format!("❌ {:.0}% of the audio is very quiet", stats.quiet_pct),
+            Some(LevelProblem::Clipping) => format!("❌ {:.1}% of the audio is clipped", stats.clipped_pct),
+            Some(LevelProblem::LowLevel) => "❌ overall level too low".to_string(),
+        };
+        let _ = writeln!(out, "   Verdict:    {}", verdict);
+        let _ = writeln!(
+            out,
+            "   audio_gain: {:.1} recommended (recorded at {:.1})",
+            a.recommended_gain, a.audio_gain
+        );
+        if let Some(secs) = self.window_secs {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "   Windows of {} s:", secs);
+            for w in &self.windows {
+                let _ = writeln!(
+                    out,
+                    "   {:>8.1}s  RMS {:>6.1} dBFS  peak {:>6.1}  clipped {:>5.2}%  quiet {:>5.1}%",
+                    w.start_secs, dbfs(w.stats.rms), dbfs(w.stats.peak), w.stats.clipped_pct, w.stats.quiet_pct
+                );
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn sine(amplitude: f32, seconds: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 200.0 * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn good_levels_keep_the_gain() {
+        let analysis = analyze(&sine(0.2, 2.0), RATE, 3.0);
+        let stats = &analysis.stats;
+        assert_eq!(stats.samples, 32000);
+        assert!((stats.duration_secs - 2.0).abs() < 1e-9);
+        assert!((stats.rms - 0.2 / 2f32.sqrt()).abs() < 1e-3);
+        assert!((stats.peak - 0.2).abs() < 1e-3);
+        assert_eq!(stats.clipped_pct, 0.0);
+        let dist = &stats.distribution;
+        let total = dist.very_quiet_pct + dist.normal_pct + dist.loud_pct + dist.very_loud_pct + dist.clipped_pct;
+        assert!((total - 100.0).abs() < 1e-3);
+        assert_eq!(analysis.problem, None);
+        assert_eq!(analysis.recommended_gain, 3.0);
+    }
+
+    #[test]
+    fn quiet_and_clipped_recordings_get_new_gains() {
+        let quiet = analyze(&sine(0.005, 1.0), RATE, 2.0);
+        assert_eq!(quiet.problem, Some(LevelProblem::MostlyQuiet));
+        assert!(quiet.recommended_gain > 2.0);
+
+        let low = analyze(&sine(0.05, 1.0), RATE, 2.0);
+        assert_eq!(low.problem, Some(LevelProblem::LowLevel));
+        assert!((low.recommended_gain - 2.0 * TARGET_RMS / low.stats.rms).abs() < 1e-3);
+
+        let clipped: Vec<f32> = sine(3.0, 1.0).iter().map(|s| s.clamp(-1.0, 1.0)).collect();
+        let clipped = analyze(&clipped, RATE, 4.0);
+        assert_eq!(clipped.problem, Some(LevelProblem::Clipping));
+        assert!(clipped.recommended_gain < 4.0);
+    }
+
+    #[test]
+    fn streaming_matches_whole_buffer_and_splits_minutes() {
+        let signal = [sine(0.2, 60.0), sine(0.02, 30.0)].concat();
+        let mut analyzer = AudioAnalyzer::new(RATE);
+        for chunk in signal.chunks(1234) {
+            analyzer.push(chunk);
+        }
+        let streamed = analyzer.finish(2.0);
+        assert_eq!(streamed, analyze(&signal, RATE, 2.0));
+
+        assert_eq!(streamed.minutes.len(), 2);
+        assert_eq!(streamed.minutes[0].stats.samples, 60 * RATE as u64);
+        assert!((streamed.minutes[1].stats.duration_secs - 30.0).abs() < 1e-9);
+        assert!(streamed.minutes[1].stats.rms < streamed.minutes[0].stats.rms / 5.0);
+
+        let json: serde_json::Value = serde_json::to_value(&streamed).unwrap();
+        assert_eq!(json["sample_rate"], 16000);
+        assert!(json["rms"].is_number());
+        assert!(json["distribution"]["clipped_pct"].is_number());
+        assert_eq!(json["minutes"][1]["minute"], 1);
+        assert!(json["problem"].is_null());
+    }
+
+    /// Deterministic white noise with the given RMS.
+    fn noise(rms: f32, seconds: f32) -> Vec<f32> {
+        let mut state = 0x1234_5678u32;
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * rms * 3f32.sqrt()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn report_estimates_snr_windows_and_dc_offset() {
+        let background = noise(0.01, 3.0);
+        let mut signal = background.clone();
+        let speech = sine(0.2, 1.0);
+        for (s, v) in signal[RATE as usize..].iter_mut().zip(&speech) {
+            *s += v;
+        }
+        let audio = WavAudio { samples: signal, sample_rate: RATE, channels: 1 };
+
+        let report = quality_report("test.wav", &audio, 2.0, Some(1.0), 6.0);
+        // 0.02 speech power over 0.0001 noise power
+        let snr = report.snr.snr_db.unwrap();
+        assert!((snr - 23.0).abs() < 1.5, "snr {}", snr);
+        assert!((report.snr.speech_pct - 33.3).abs() < 5.0, "speech {}%", report.snr.speech_pct);
+        assert!(report.dc_offset_ok);
+
+        assert_eq!(report.windows.len(), 3);
+        assert_eq!(report.windows[1].start_secs, 1.0);
+        assert!(report.windows[1].stats.rms > 10.0 * report.windows[0].stats.rms);
+
+        let text = report.to_text();
+        assert!(text.contains("SNR:"), "{}", text);
+        assert!(text.contains("Windows of 1 s"), "{}", text);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["windows"][2]["start_secs"], 2.0);
+        assert!(json["snr"]["snr_db"].is_number());
+
+        let offset: Vec<f32> = audio.samples.iter().map(|s| s + 0.05).collect();
+        let offset = WavAudio { samples: offset, ..audio };
+        let report = quality_report("offset.wav", &offset, 2.0, None, 6.0);
+        assert!(!report.dc_offset_ok);
+        assert!((report.analysis.stats.dc_offset - 0.05).abs() < 1e-3);
+        assert!(report.windows.is_empty());
+        assert!(report.to_text().contains("dc_blocker"));
+
+        // Constant tone: no pauses to measure the noise in
+        let tone = WavAudio { samples: sine(0.2, 1.0), sample_rate: RATE, channels: 1 };
+        let report = quality_report("tone.wav", &tone, 2.0, None, 6.0);
+        assert_eq!(report.snr.snr_db, None);
+    }
+
+    #[test]
+    fn load_wav_mixes_int_channels_to_mono() {
+        let path = std::env::temp_dir().join(format!("pstt-analysis-test-{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 2, sample_rate: 8000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..100 {
+            writer.write_sample(16384i16).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let audio = load_wav(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!((audio.sample_rate, audio.channels, audio.samples.len()), (8000, 2, 100));
+        assert!(audio.samples.iter().all(|&s| (s - 0.25).abs() < 1e-6));
+    }
+}
@@ -1,5 +1,31 @@
 use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{FromSample, Sample, SizedSample};
 use anyhow::{Result, Context};
+use serde::Serialize;
+use std::sync::Arc;
+use crate::buffers::CaptureRing;
+use crate::capture::CaptureHealth;
+
+/// One supported stream configuration range reported by a device.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamConfigRange {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub sample_format: String,
+}
+
+/// Everything cpal can tell us about an input device.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceCapabilities {
+    /// 1-based position, matching the device menu and `--device`.
+    pub number: usize,
+    pub name: String,
+    pub is_default: bool,
+    /// The config `RecordingSession` will open the device with.
+    pub default_config: Option<StreamConfigRange>,
+    pub supported_configs: Vec<StreamConfigRange>,
+}
 
 pub fn list_input_devices() -> Result<Vec<(usize, String)>> {
     let host = cpal::default_host();
@@ -27,3 +53,394 @@ pub fn get_device_info(device: &cpal::Device) -> Result<(String, cpal::Supported
     let config = device.default_input_config()?;
     Ok((name, config))
 }
+
+/// Query every input device for its supported and default stream configs.
+///
+/// Devices that fail to report their configs (e.g. just unplugged) are still
+/// listed, with empty config lists, so the numbering matches the menu.
+pub fn list_input_device_capabilities() -> Result<Vec<DeviceCapabilities>> {
+    let host = cpal::default_host();
+    let default_id = host.default_input_device().and_then(|d| d.id().ok());
+
+    let devices = host.input_devices()?
+        .enumerate()
+        .map(|(i, device)| {
+            let name = device.description().map(|desc| desc.to_string()).unwrap_or_else(|_| "Unknown".to_string());
+            let is_default = match (&default_id, device.id()) {
+                (Some(default_id), Ok(id)) => *default_id == id,
+                _ => false,
+            };
+            let default_config = device.default_input_config().ok().map(|c| StreamConfigRange {
+                channels: c.channels(),
+                min_sample_rate: c.sample_rate(),
+                max_sample_rate: c.sample_rate(),
+                sample_format: c.sample_format().to_string(),
+            });
+            let supported_configs = match device.supported_input_configs() {
+                Ok(configs) => configs
+                    .map(|c| StreamConfigRange {
+                        channels: c.channels(),
+                        min_sample_rate: c.min_sample_rate(),
+                        max_sample_rate: c.max_sample_rate(),
+                        sample_format: c.sample_format().to_string(),
+                    })
+                    .collect(),
+                Err(e) => {
+                    log::warn!("Could not query configs for {}: {}", name, e);
+                    Vec::new()
+                }
+            };
+            DeviceCapabilities {
+                number: i + 1,
+                name,
+                is_default,
+                default_config,
+                supported_configs,
+            }
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+/// Render a human-readable capability listing for `pstt devices`.
+pub fn format_device_capabilities(devices: &[DeviceCapabilities]) -> String {
+    let mut out = String::new();
+    for device in devices {
+        let marker = if device.is_default { " (default)" } else { "" };
+        out.push_str(&format!("{}. {}{}\n", device.number, device.name, marker));
+
+        match &device.default_config {
+            Some(c) => {
+                out.push_str(&format!(
+                    "     default:   {} ch, {} Hz, {}\n",
+                    c.channels, c.min_sample_rate, c.sample_format
+                ));
+                out.push_str(&format!("     channels:  {}\n", channel_layout(c.channels)));
+            }
+            None => out.push_str("     default:   unavailable\n"),
+        }
+
+        if device.supported_configs.is_empty() {
+            out.push_str("     supported: none reported\n");
+        }
+        for c in &device.supported_configs {
+            let rate = if c.min_sample_rate == c.max_sample_rate {
+                format!("{} Hz", c.min_sample_rate)
+            } else {
+                format!("{}-{} Hz", c.min_sample_rate, c.max_sample_rate)
+            };
+            out.push_str(&format!(
+                "     supported: {} ch, {}, {}\n",
+                c.channels, rate, c.sample_format
+            ));
+        }
+    }
+    out
+}
+
+/// Open `device` with its negotiated config and push normalized f32 samples
+/// into `raw_queue`, whatever sample format the hardware delivers.  Stream
+/// liveness is reported through `health` for the capture supervisor.
+///
+/// The stream holds the ring's producer until it is dropped.  The callback
+/// only converts samples into the ring: overruns are counted by the ring,
+/// never logged from the audio thread.
+pub fn build_capture_stream(
+    device: &cpal::Device,
+    stream_config: &cpal::SupportedStreamConfig,
+    raw_queue: &Arc<CaptureRing>,
+    health: Arc<CaptureHealth>,
+) -> Result<cpal::Stream> {
+    let config: cpal::StreamConfig = stream_config.clone().into();
+    match stream_config.sample_format() {
+        cpal::SampleFormat::I8 => build_typed_stream::<i8>(device, &config, raw_queue, health),
+        cpal::SampleFormat::I16 => build_typed_stream::<i16>(device, &config, raw_queue, health),
+        cpal::SampleFormat::I24 => build_typed_stream::<cpal::I24>(device, &config, raw_queue, health),
+        cpal::SampleFormat::I32 => build_typed_stream::<i32>(device, &config, raw_queue, health),
+        cpal::SampleFormat::I64 => build_typed_stream::<i64>(device, &config, raw_queue, health),
+        cpal::SampleFormat::U8 => build_typed_stream::<u8>(device, &config, raw_queue, health),
+        cpal::SampleFormat::U16 => build_typed_stream::<u16>(device, &config, raw_queue, health),
+        cpal::SampleFormat::U24 => build_typed_stream::<cpal::U24>(device, &config, raw_queue, health),
+        cpal::SampleFormat::U32 => build_typed_stream::<u32>(device, &config, raw_queue, health),
+        cpal::SampleFormat::U64 => build_typed_stream::<u64>(device, &config, raw_queue, health),
+        cpal::SampleFormat::F32 => build_typed_stream::<f32>(device, &config, raw_queue, health),
+        cpal::SampleFormat::F64 => build_typed_stream::<f64>(device, &config, raw_queue, health),
+        other => anyhow::bail!(
+            "Unsupported capture sample format: {} (device default config: {} ch, {} Hz)",
+            other,
+            stream_config.channels(),
+            stream_config.sample_rate()
+        ),
+    }
+}
+
+fn build_typed_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    raw_queue: &Arc<CaptureRing>,
+    health: Arc<CaptureHealth>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let mut producer = raw_queue.producer()?;
+    let error_health = Arc::clone(&health);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &_| {
+            health.mark_callback();
+            producer.push_iter(samples_to_f32(data));
+        },
+        move |err| match err {
+            cpal::StreamError::BufferUnderrun => log::warn!("Stream error: {}", err),
+            cpal::StreamError::DeviceNotAvailable | cpal::StreamError::StreamInvalidated => {
+                log::error!("Stream error: {}", err);
+                error_health.mark_failed();
+            }
+            // Backend errors may be transient; the stall timeout catches a dead stream
+            _ => log::error!("Stream error: {}", err),
+        },
+        None,
+    ).with_context(|| format!("Failed to open {} input stream", T::FORMAT))?;
+    Ok(stream)
+}
+
+/// Convert a buffer of any cpal sample type to f32 in `-1.0..=1.0`, lazily
+/// so the audio callback can write straight into the capture ring.
+pub fn samples_to_f32<T>(data: &[T]) -> impl ExactSizeIterator<Item = f32> + '_
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    data.iter().map(|&s| s.to_sample::<f32>())
+}
+
+/// Describe the 0-based channel numbering used by `input_channels`.
+pub fn channel_layout(channels: u16) -> String {
+    match channels {
+        0 => "none".to_string(),
+        1 => "mono (0)".to_string(),
+        2 => "stereo (0 = left, 1 = right)".to_string(),
+        n => format!("{} inputs (0-{})", n, n - 1),
+    }
+}
+
+/// Return the host's default input device together with its display name.
+pub fn default_input_device() -> Result<(cpal::Device, String)> {
+    let host = cpal::default_host();
+    let device = host.default_input_device()
+        .context("No default input device available")?;
+    let name = device.description().map(|desc| desc.to_string()).unwrap_or_else(|_| "Unknown".to_string());
+    Ok((device, name))
+}
+
+/// Resolve a device selector (from `--device` or `input_device`) to a device.
+///
+/// See `match_device` for the matching rules.  When nothing matches, a
+/// warning is logged and the host's default input device is used instead.
+pub fn resolve_device(selector: &str) -> Result<(cpal::Device, String)> {
+    // Asked for by name, so no fallback warning
+    if is_default_selector(selector) {
+        return default_input_device();
+    }
+    let devices = list_input_devices()?;
+
+    match match_device(&devices, selector)? {
+        Some(index) => {
+            let device = select_device(index)?;
+            Ok((device, devices[index].1.clone()))
+        }
+        None => {
+            log::warn!(
+                "No input device matches \"{}\"; falling back to the default input device",
+                selector
+            );
+            default_input_device().with_context(|| {
+                format!("No input device matches \"{}\" and no default device is available", selector)
+            })
+        }
+    }
+}
+
+/// An empty selector or `"default"` (any case) means the host default device.
+fn is_default_selector(selector: &str) -> bool {
+    let selector = selector.trim();
+    selector.is_empty() || selector.eq_ignore_ascii_case("default")
+}
+
+/// Match `selector` against a device list as returned by `list_input_devices`.
+///
+/// Matching order:
+///   1. `"default"` → `Ok(None)` (caller uses the host default device)
+///   2. a number → the 1-based position shown in the device menu
+///   3. an exact name (case-insensitive)
+///   4. a unique case-insensitive substring of a name
+///
+/// Returns `Ok(None)` when nothing matches and an error when the index is
+/// out of range or the substring matches more than one device.
+pub fn match_device(devices: &[(usize, String)], selector: &str) -> Result<Option<usize>> {
+    if is_default_selector(selector) {
+        return Ok(None);
+    }
+    let selector = selector.trim();
+
+    if let Ok(number) = selector.parse::<usize>() {
+        if number == 0 || number > devices.len() {
+            anyhow::bail!(
+                "Device index {} is out of range (1-{})",
+                number,
+                devices.len()
+            );
+        }
+        return Ok(Some(devices[number - 1].0));
+    }
+
+    let needle = selector.to_lowercase();
+
+    if let Some((i, _)) = devices.iter().find(|(_, name)| name.to_lowercase() == needle) {
+        return Ok(Some(*i));
+    }
+
+    let matches: Vec<&(usize, String)> = devices.iter()
+        .filter(|(_, name)| name.to_lowercase().contains(&needle))
+        .collect();
+
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(Some(matches[0].0)),
+        _ => {
+            let names: Vec<&str> = matches.iter().map(|(_, name)| name.as_str()).collect();
+            anyhow::bail!(
+                "Device selector \"{}\" is ambiguous, it matches: {}",
+                selector,
+                names.join(", ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> Vec<(usize, String)> {
+        vec![
+            (0, "Built-in Microphone".to_string()),
+            (1, "USB Headset".to_string()),
+            (2, "USB Headset Monitor".to_string()),
+            (3, "Loopback".to_string()),
+        ]
+    }
+
+    fn range(channels: u16, min: u32, max: u32, format: &str) -> StreamConfigRange {
+        StreamConfigRange {
+            channels,
+            min_sample_rate: min,
+            max_sample_rate: max,
+            sample_format: format.to_string(),
+        }
+    }
+
+    #[test]
+    fn capability_listing_marks_default_and_ranges() {
+        let devices = vec![
+            DeviceCapabilities {
+                number: 1,
+                name: "USB Interface".to_string(),
+                is_default: true,
+                default_config: Some(range(2, 44100, 44100, "i16")),
+                supported_configs: vec![range(2, 8000, 96000, "i16"), range(1, 48000, 48000, "f32")],
+            },
+            DeviceCapabilities {
+                number: 2,
+                name: "Broken".to_string(),
+                is_default: false,
+                default_config: None,
+                supported_configs: Vec::new(),
+            },
+        ];
+
+        let text = format_device_capabilities(&devices);
+        assert!(text.contains("1. USB Interface (default)"));
+        assert!(text.contains("default:   2 ch, 44100 Hz, i16"));
+        assert!(text.contains("channels:  stereo (0 = left, 1 = right)"));
+        assert!(text.contains("supported: 2 ch, 8000-96000 Hz, i16"));
+        assert!(text.contains("supported: 1 ch, 48000 Hz, f32"));
+        assert!(text.contains("2. Broken\n"));
+        assert!(text.contains("none reported"));
+
+        let json: serde_json::Value = serde_json::to_value(&devices).unwrap();
+        assert_eq!(json[0]["is_default"], true);
+        assert_eq!(json[0]["supported_configs"][0]["max_sample_rate"], 96000);
+        assert!(json[1]["default_config"].is_null());
+    }
+
+    #[test]
+    fn channel_layout_is_zero_based() {
+        assert_eq!(channel_layout(1), "mono (0)");
+        assert_eq!(channel_layout(4), "4 inputs (0-3)");
+    }
+
+    #[test]
+    fn integer_formats_are_normalized() {
+        let i16s: Vec<f32> = samples_to_f32(&[i16::MIN, 0, i16::MAX]).collect();
+        assert_eq!(i16s[0], -1.0);
+        assert_eq!(i16s[1], 0.0);
+        assert!((i16s[2] - 1.0).abs() < 1e-4);
+
+        let i32s: Vec<f32> = samples_to_f32(&[i32::MIN, 0, i32::MAX / 2]).collect();
+        assert_eq!(i32s[0], -1.0);
+        assert_eq!(i32s[1], 0.0);
+        assert!((i32s[2] - 0.5).abs() < 1e-4);
+
+        // Unsigned formats are centred on the midpoint
+        let u16s: Vec<f32> = samples_to_f32(&[0u16, 32768, u16::MAX]).collect();
+        assert_eq!(u16s[0], -1.0);
+        assert_eq!(u16s[1], 0.0);
+        assert!((u16s[2] - 1.0).abs() < 1e-4);
+
+        assert_eq!(samples_to_f32(&[0.25f32, -0.5]).collect::<Vec<_>>(), vec![0.25, -0.5]);
+    }
+
+    #[test]
+    fn matches_by_one_based_index() {
+        assert_eq!(match_device(&devices(), "1").unwrap(), Some(0));
+        assert_eq!(match_device(&devices(), "4").unwrap(), Some(3));
+        assert!(match_device(&devices(), "0").is_err());
+        assert!(match_device(&devices(), "5").is_err());
+    }
+
+    #[test]
+    fn exact_name_wins_over_substring() {
+        // "USB Headset" is also a substring of "USB Headset Monitor"
+        assert_eq!(match_device(&devices(), "usb headset").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn unique_substring_matches() {
+        assert_eq!(match_device(&devices(), "loop").unwrap(), Some(3));
+        assert_eq!(match_device(&devices(), "monitor").unwrap(), Some(2));
+    }
+
+    #[test]
+    fn ambiguous_substring_errors() {
+        let err = match_device(&devices(), "USB").unwrap_err();
+        assert!(err.to_string().contains("ambiguous"));
+    }
+
+    #[test]
+    fn empty_and_default_select_the_default_device() {
+        assert!(is_default_selector("default"));
+        assert!(is_default_selector(" Default "));
+        assert!(is_default_selector(""));
+        assert!(!is_default_selector("Default Mic"));
+    }
+
+    #[test]
+    fn no_match_or_default_returns_none() {
+        assert_eq!(match_device(&devices(), "Studio Mic").unwrap(), None);
+        assert_eq!(match_device(&devices(), "default").unwrap(), None);
+    }
+}
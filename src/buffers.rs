@@ -1,59 +1,801 @@
+use anyhow::Result;
+use ringbuf::traits::{Consumer, Observer, Producer};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, Condvar};
+use std::thread::Thread;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
+use crate::bus::{AudioBus, Tap};
+use crate::config::Config;
+use crate::source::SourceFormat;
+
+/// What `BlockingQueue::push_slice` does with a batch that doesn't fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Reject the incoming batch.
+    DropNewest,
+    /// Discard the oldest queued items to make room.
+    DropOldest,
+    /// Wait up to the timeout for the consumer to make room, then reject
+    /// the batch.
+    BlockWithTimeout(Duration),
+}
+
+impl OverflowPolicy {
+    /// Parse a config value; `Config::validate` has already rejected
+    /// unknown names.
+    pub fn from_name(name: &str, block_timeout: Duration) -> Self {
+        match name {
+            "drop_oldest" => Self::DropOldest,
+            "block_with_timeout" => Self::BlockWithTimeout(block_timeout),
+            _ => Self::DropNewest,
+        }
+    }
+}
+
+/// Bounded queue between two threads.
+///
+/// Items lost to overflow are remembered as gaps at their position in the
+/// stream, so a consumer that needs an unbroken timeline (the WAV writer)
+/// can fill them with silence via `pop_into_timeout`.
 pub struct BlockingQueue<T> {
-    queue: Mutex<VecDeque<T>>,
+    queue: Mutex<QueueState<T>>,
+    /// Signalled when items arrive or the queue is closed
     condvar: Condvar,
+    /// Signalled when a consumer makes room
+    not_full: Condvar,
     max_size: usize,
+    policy: OverflowPolicy,
+}
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    /// Stream position of `items[0]`, counting dropped items too
+    taken: u64,
+    /// Dropped runs as (position, length): `length` items were lost right
+    /// before the item at `position`
+    gaps: VecDeque<(u64, usize)>,
+    dropped: u64,
+    /// Items pushed after the queue was closed
+    discarded: u64,
+}
+
+impl<T> QueueState<T> {
+    /// Record `count` items lost right before position `at`.
+    fn add_gap(&mut self, at: u64, count: usize) {
+        self.dropped += count as u64;
+        match self.gaps.back_mut() {
+            Some((pos, len)) if *pos == at => *len += count,
+            _ => self.gaps.push_back((at, count)),
+        }
+    }
+
+    /// Discard the `count` oldest items, leaving a gap where they were.
+    fn drop_oldest(&mut self, count: usize) {
+        self.items.drain(..count);
+        self.taken += count as u64;
+        self.dropped += count as u64;
+        let gap = count + self.take_gap();
+        if gap > 0 {
+            self.gaps.push_front((self.taken, gap));
+        }
+    }
+
+    /// Remove and return the length of the gaps at the front of the queue.
+    fn take_gap(&mut self) -> usize {
+        let mut gap = 0;
+        while let Some(&(pos, len)) = self.gaps.front() {
+            if pos > self.taken {
+                break;
+            }
+            gap += len;
+            self.gaps.pop_front();
+        }
+        gap
+    }
+
+    /// Take the gap at the front and up to `max_count` items after it,
+    /// stopping at the next gap.  The items replace the contents of `out`.
+    fn take_into(&mut self, out: &mut Vec<T>, max_count: usize) -> usize {
+        let gap = self.take_gap();
+        let until_gap = self.gaps.front().map_or(usize::MAX, |&(pos, _)| (pos - self.taken) as usize);
+        let count = self.items.len().min(max_count).min(until_gap);
+        self.taken += count as u64;
+        out.clear();
+        out.extend(self.items.drain(..count));
+        gap
+    }
+
+    fn take(&mut self, max_count: usize) -> (usize, Vec<T>) {
+        let mut items = Vec::new();
+        let gap = self.take_into(&mut items, max_count);
+        (gap, items)
+    }
+}
+
+/// Why a timed pop returned nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopError {
+    /// Nothing arrived before the timeout.
+    Timeout,
+    /// The queue was closed and everything pushed before has been taken.
+    Closed,
 }
 
 impl<T> BlockingQueue<T> {
-    pub fn new(max_size: usize) -> Arc<Self> {
+    pub fn new(max_size: usize, policy: OverflowPolicy) -> Arc<Self> {
         Arc::new(Self {
-            queue: Mutex::new(VecDeque::with_capacity(max_size)),
+            queue: Mutex::new(QueueState {
+                items: VecDeque::with_capacity(max_size),
+                closed: false,
+                taken: 0,
+                gaps: VecDeque::new(),
+                dropped: 0,
+                discarded: 0,
+            }),
             condvar: Condvar::new(),
+            not_full: Condvar::new(),
             max_size,
+            policy,
         })
     }
     
-    pub fn push(&self, items: Vec<T>) -> bool {
+    /// Copy `items` into the queue, applying the overflow policy when they
+    /// don't fit.  Returns false when the batch was rejected.  The queue's
+    /// storage is allocated up front, so this only allocates when
+    /// recording a gap.
+    pub fn push_slice(&self, items: &[T]) -> bool
+    where
+        T: Clone,
+    {
         let mut queue = self.queue.lock().unwrap();
+        let mut items = items;
+        
+        if !queue.closed && queue.items.len() + items.len() > self.max_size {
+            match self.policy {
+                OverflowPolicy::DropNewest => {}
+                OverflowPolicy::DropOldest => {
+                    let excess = queue.items.len() + items.len() - self.max_size;
+                    let from_queue = excess.min(queue.items.len());
+                    queue.drop_oldest(from_queue);
+                    // A batch larger than the whole queue keeps only its newest part
+                    let skip = excess - from_queue;
+                    if skip > 0 {
+                        let at = queue.taken;
+                        queue.add_gap(at, skip);
+                        items = &items[skip..];
+                    }
+                    log::debug!("Queue overflow! Dropped {} oldest items", excess);
+                }
+                OverflowPolicy::BlockWithTimeout(timeout) => {
+                    queue = self.not_full
+                        .wait_timeout_while(queue, timeout, |q| {
+                            !q.closed && q.items.len() + items.len() > self.max_size
+                        })
+                        .unwrap()
+                        .0;
+                }
+            }
+        }
         
-        if queue.len() + items.len() > self.max_size {
-            log::warn!("Queue overflow! Dropping {} items", items.len());
+        if queue.closed {
+            queue.discarded += items.len() as u64;
+            return false;
+        }
+        if queue.items.len() + items.len() > self.max_size {
+            let at = queue.taken + queue.items.len() as u64;
+            queue.add_gap(at, items.len());
+            log::debug!("Queue overflow! Dropping {} items", items.len());
             return false;
         }
         
-        queue.extend(items);
+        queue.items.extend(items.iter().cloned());
         self.condvar.notify_one();
         true
     }
     
     pub fn try_pop_batch(&self, max_count: usize) -> Option<Vec<T>> {
-        let mut queue = self.queue.lock().unwrap();
+        let (_, items) = self.queue.lock().unwrap().take(max_count);
+        self.not_full.notify_one();
+        (!items.is_empty()).then_some(items)
+    }
+    
+    /// Wait up to `timeout` for items and take up to `max_count` of them.
+    /// Items pushed before `close` are still returned; only an empty,
+    /// closed queue reports `PopError::Closed`.  Gaps are skipped.
+    pub fn pop_batch_timeout(&self, max_count: usize, timeout: Duration) -> Result<Vec<T>, PopError> {
+        let queue = self.queue.lock().unwrap();
+        let (mut queue, _) = self.condvar
+            .wait_timeout_while(queue, timeout, |q| q.items.is_empty() && !q.closed)
+            .unwrap();
         
-        if queue.is_empty() {
-            return None;
+        if queue.items.is_empty() {
+            return Err(if queue.closed { PopError::Closed } else { PopError::Timeout });
         }
+        let (_, items) = queue.take(max_count);
+        self.not_full.notify_one();
+        Ok(items)
+    }
+    
+    /// Like `pop_batch_timeout`, but replaces the contents of `out` instead
+    /// of allocating a batch, and returns how many items were dropped right
+    /// before it.  `out` may be left empty when only a gap is left.
+    pub fn pop_into_timeout(&self, out: &mut Vec<T>, max_count: usize, timeout: Duration) -> Result<usize, PopError> {
+        out.clear();
+        let queue = self.queue.lock().unwrap();
+        let (mut queue, _) = self.condvar
+            .wait_timeout_while(queue, timeout, |q| q.items.is_empty() && q.gaps.is_empty() && !q.closed)
+            .unwrap();
         
-        let count = queue.len().min(max_count);
-        Some(queue.drain(..count).collect())
+        let gap = queue.take_into(out, max_count);
+        if gap == 0 && out.is_empty() {
+            return Err(if queue.closed { PopError::Closed } else { PopError::Timeout });
+        }
+        self.not_full.notify_one();
+        Ok(gap)
+    }
+    
+    /// Mark the end of the stream: later pushes are rejected, and waiting
+    /// consumers wake up to drain what is left.
+    pub fn close(&self) {
+        self.queue.lock().unwrap().closed = true;
+        self.condvar.notify_all();
+        self.not_full.notify_all();
     }
     
+    /// Total items lost to overflow.
+    pub fn dropped(&self) -> u64 {
+        self.queue.lock().unwrap().dropped
+    }
+    
+    /// Total items pushed after the queue was closed, e.g. because its
+    /// consumer had already exited.
+    pub fn discarded(&self) -> u64 {
+        self.queue.lock().unwrap().discarded
+    }
+}
+
+/// Closes a queue when dropped.  A consumer thread holds one for its input
+/// queue, so however it exits, even early on an error, the producer stops
+/// filling (or waiting on) a queue nobody reads any more.
+pub struct CloseOnDrop<T>(pub Arc<BlockingQueue<T>>);
+
+impl<T> Drop for CloseOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Lock-free single-producer single-consumer queue between a capture
+/// source and the resampler.
+///
+/// The producer side is safe to use from a real-time audio callback: it
+/// never blocks, allocates or logs.  A batch that doesn't fit is dropped
+/// whole, so interleaved frames stay aligned, and its size is added to an
+/// atomic overrun counter for a normal thread to report.  Its position is
+/// passed on through a small second ring, so the consumer can put
+/// silence in its place.
+///
+/// There is at most one producer and one consumer at a time.  Each opened
+/// stream takes the producer and hands it back when dropped, so a
+/// reconnected device writes into the same ring.
+///
+/// A consumer waiting in `CaptureConsumer::pop_into_timeout` parks its
+/// thread.  Only then does a push unpark it, which is a single
+/// non-blocking wake-up, so a busy consumer costs the producer nothing.
+pub struct CaptureRing {
+    ring: Arc<HeapRb<f32>>,
+    /// (position, length) of each overrun, as in `BlockingQueue`
+    gaps: Arc<HeapRb<(u64, usize)>>,
+    captured: AtomicU64,
+    consumed: AtomicU64,
+    overruns: AtomicU64,
+    closed: AtomicBool,
+    /// Set while the consumer is parked (or about to park) in `waiter`
+    waiting: AtomicBool,
+    waiter: Mutex<Option<Thread>>,
+}
+
+/// Overruns a `CaptureRing` can record before the consumer catches up.
+/// Beyond that, further overruns are merged into the last one.
+const MAX_PENDING_GAPS: usize = 64;
+
+impl CaptureRing {
+    pub fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            ring: Arc::new(HeapRb::new(capacity.max(1))),
+            gaps: Arc::new(HeapRb::new(MAX_PENDING_GAPS)),
+            captured: AtomicU64::new(0),
+            consumed: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            waiting: AtomicBool::new(false),
+            waiter: Mutex::new(None),
+        })
+    }
+
+    /// Take the write side.  Fails while another producer (e.g. a stream
+    /// that is still shutting down) holds it.
+    pub fn producer(self: &Arc<Self>) -> Result<CaptureProducer> {
+        if self.ring.write_is_held() {
+            anyhow::bail!("Capture ring already has a producer");
+        }
+        Ok(CaptureProducer {
+            prod: HeapProd::new(Arc::clone(&self.ring)),
+            gaps: HeapProd::new(Arc::clone(&self.gaps)),
+            position: self.captured(),
+            pending_gap: None,
+            owner: Arc::clone(self),
+        })
+    }
+
+    /// Take the read side.  Fails while another consumer holds it.
+    pub fn consumer(self: &Arc<Self>) -> Result<CaptureConsumer> {
+        if self.ring.read_is_held() {
+            anyhow::bail!("Capture ring already has a consumer");
+        }
+        Ok(CaptureConsumer {
+            cons: HeapCons::new(Arc::clone(&self.ring)),
+            gaps: HeapCons::new(Arc::clone(&self.gaps)),
+            position: self.consumed.load(Ordering::Relaxed),
+            owner: Arc::clone(self),
+        })
+    }
+
+    /// Mark the end of the stream once capture has stopped.  The consumer
+    /// drains what is left, then sees `PopError::Closed`.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.wake();
+    }
+
+    /// Unpark the consumer if it is waiting.  Never blocks: the consumer
+    /// only holds `waiter` before it announces itself in `waiting`.
+    fn wake(&self) {
+        // Pairs with the fence in `pop_into_timeout`: either the consumer
+        // sees what was just pushed, or this sees it waiting
+        fence(Ordering::SeqCst);
+        if self.waiting.swap(false, Ordering::Relaxed) {
+            if let Ok(waiter) = self.waiter.try_lock() {
+                if let Some(thread) = waiter.as_ref() {
+                    thread.unpark();
+                }
+            }
+        }
+    }
+
+    /// True while a stream or producer thread holds the write side.
+    pub fn has_producer(&self) -> bool {
+        self.ring.write_is_held()
+    }
+
+    /// Total samples accepted into the ring.
+    pub fn captured(&self) -> u64 {
+        self.captured.load(Ordering::Relaxed)
+    }
+
+    /// Total samples dropped because the ring was full.
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+}
+
+/// Write side of a `CaptureRing`.
+pub struct CaptureProducer {
+    prod: HeapProd<f32>,
+    gaps: HeapProd<(u64, usize)>,
+    /// Samples accepted so far, the position of the next one
+    position: u64,
+    /// An overrun not yet handed to the consumer
+    pending_gap: Option<(u64, usize)>,
+    owner: Arc<CaptureRing>,
+}
+
+impl CaptureProducer {
+    /// Push every sample of `samples`, or none of them if they don't all
+    /// fit.  Converting through an iterator lets callers write straight
+    /// from the device's sample format without an intermediate buffer.
+    pub fn push_iter<I>(&mut self, samples: I) -> bool
+    where
+        I: ExactSizeIterator<Item = f32>,
+    {
+        let count = samples.len();
+        if self.prod.vacant_len() < count {
+            self.owner.overruns.fetch_add(count as u64, Ordering::Relaxed);
+            self.pending_gap.get_or_insert((self.position, 0)).1 += count;
+            return false;
+        }
+        // The gap must be visible before the samples that follow it
+        self.flush_gap();
+        self.prod.push_iter(samples);
+        self.position += count as u64;
+        self.owner.captured.fetch_add(count as u64, Ordering::Relaxed);
+        self.owner.wake();
+        true
+    }
+
+    pub fn push_slice(&mut self, samples: &[f32]) -> bool {
+        self.push_iter(samples.iter().copied())
+    }
+
+    /// Number of samples that can still be pushed without overflowing.
+    pub fn free_space(&self) -> usize {
+        self.prod.vacant_len()
+    }
+
+    fn flush_gap(&mut self) {
+        if let Some(gap) = self.pending_gap {
+            if self.gaps.try_push(gap).is_ok() {
+                self.pending_gap = None;
+            }
+        }
+    }
+}
+
+impl Drop for CaptureProducer {
+    fn drop(&mut self) {
+        // Overruns right before a stop still belong to the recording
+        self.flush_gap();
+    }
+}
+
+/// Read side of a `CaptureRing`.
+pub struct CaptureConsumer {
+    cons: HeapCons<f32>,
+    gaps: HeapCons<(u64, usize)>,
+    /// Samples taken so far, the position of the next one
+    position: u64,
+    owner: Arc<CaptureRing>,
+}
+
+impl CaptureConsumer {
+    /// Replace the contents of `out` with up to `max_count` samples, stopping
+    /// at the next overrun.  Returns the number of samples lost to overruns
+    /// right before `out`, or `None` (leaving `out` empty) when there is
+    /// nothing to take.
+    pub fn pop_into(&mut self, out: &mut Vec<f32>, max_count: usize) -> Option<usize> {
+        // Read the sample count first: every gap before those samples is then visible
+        let available = self.cons.occupied_len();
+        let mut gap = 0;
+        while let Some(&(pos, len)) = self.gaps.try_peek() {
+            if pos > self.position {
+                break;
+            }
+            gap += len;
+            self.gaps.try_pop();
+        }
+        let until_gap = self.gaps.try_peek().map_or(usize::MAX, |&(pos, _)| (pos - self.position) as usize);
+        let count = available.min(max_count).min(until_gap);
+        out.clear();
+        out.resize(count, 0.0);
+        let popped = self.cons.pop_slice(out);
+        out.truncate(popped);
+        self.position += popped as u64;
+        self.owner.consumed.store(self.position, Ordering::Relaxed);
+        (gap > 0 || popped > 0).then_some(gap)
+    }
+
+    /// Like `pop_into`, waiting up to `timeout` for samples to arrive.  The
+    /// thread sleeps until the producer pushes or the ring is closed.
+    pub fn pop_into_timeout(&mut self, out: &mut Vec<f32>, max_count: usize, timeout: Duration) -> Result<usize, PopError> {
+        let result = self.wait_into(out, max_count, Instant::now() + timeout);
+        // Done waiting, so a push no longer needs to unpark this thread
+        self.owner.waiting.store(false, Ordering::Relaxed);
+        result
+    }
+
+    fn wait_into(&mut self, out: &mut Vec<f32>, max_count: usize, deadline: Instant) -> Result<usize, PopError> {
+        let mut announced = false;
+        loop {
+            // Read the flag first: samples pushed before `close` must still be taken
+            let closed = self.owner.closed.load(Ordering::Acquire);
+            if let Some(gap) = self.pop_into(out, max_count) {
+                return Ok(gap);
+            }
+            if closed {
+                return Err(PopError::Closed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(PopError::Timeout);
+            }
+            if !announced {
+                // Announce the wait, then look once more before parking:
+                // a push that raced with the announcement is seen either
+                // here or by the producer
+                self.register_waiter();
+                self.owner.waiting.store(true, Ordering::Relaxed);
+                fence(Ordering::SeqCst);
+                announced = true;
+                continue;
+            }
+            std::thread::park_timeout(deadline - now);
+            // Unparked by `wake` (which clears the flag), timed out or woken
+            // spuriously: announce again unless still announced
+            announced = self.owner.waiting.load(Ordering::Relaxed);
+        }
+    }
+
+    /// Make the current thread the one `CaptureRing::wake` unparks.
+    fn register_waiter(&self) {
+        let current = std::thread::current();
+        let mut waiter = self.owner.waiter.lock().unwrap();
+        if waiter.as_ref().map(Thread::id) != Some(current.id()) {
+            *waiter = Some(current);
+        }
+    }
+}
+
+/// Samples each queue of an `AudioPipeline` has dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DroppedSamples {
+    /// Raw interleaved samples the resampler fell behind on
+    pub capture: u64,
+    /// Per bus subscriber, by name
+    pub consumers: Vec<(String, u64)>,
+    /// Per bus subscriber, what was published after it had stopped
+    pub stopped: Vec<(String, u64)>,
 }
 
 pub struct AudioPipeline {
-    pub raw_queue: Arc<BlockingQueue<f32>>,
-    pub resampled_queue_writer: Arc<BlockingQueue<f32>>,
-    pub resampled_queue_realtime: Arc<BlockingQueue<f32>>,
+    pub raw_queue: Arc<CaptureRing>,
+    /// The resampler publishes here; consumers subscribe with `subscribe`
+    pub bus: Arc<AudioBus>,
+    resampled_size: usize,
+    block_timeout: Duration,
 }
 
 impl AudioPipeline {
-    pub fn new(buffer_size: usize) -> Self {
+    /// A raw ring holding `config.buffer_seconds` of audio at the input's
+    /// rate and channel count, and an audio bus without subscribers.
+    pub fn new(format: SourceFormat, config: &Config) -> Self {
+        let seconds = config.buffer_seconds as f64;
+        let raw_size = (seconds * format.sample_rate as f64) as usize * format.channels.max(1) as usize;
         Self {
-            raw_queue: BlockingQueue::new(buffer_size),
-            resampled_queue_writer: BlockingQueue::new(buffer_size),
-            resampled_queue_realtime: BlockingQueue::new(buffer_size),
+            raw_queue: CaptureRing::new(raw_size),
+            bus: AudioBus::new(),
+            resampled_size: (seconds * config.sample_rate as f64) as usize,
+            block_timeout: Duration::from_millis(config.overflow_block_ms),
         }
     }
+
+    /// Subscribe a consumer to `tap` through a queue of `buffer_seconds` at
+    /// `sample_rate`, overflowing by the policy named `overflow`.
+    pub fn subscribe(&self, name: &str, tap: Tap, overflow: &str) -> Arc<BlockingQueue<f32>> {
+        let policy = OverflowPolicy::from_name(overflow, self.block_timeout);
+        self.bus.subscribe(name, tap, self.resampled_size, policy)
+    }
+
+    pub fn dropped(&self) -> DroppedSamples {
+        DroppedSamples {
+            capture: self.raw_queue.overruns(),
+            consumers: self.bus.dropped(),
+            stopped: self.bus.discarded(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pop_batch_timeout_wakes_on_push() {
+        let queue = BlockingQueue::new(16, OverflowPolicy::DropNewest);
+        let pusher = {
+            let queue = Arc::clone(&queue);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                queue.push_slice(&[1, 2, 3]);
+            })
+        };
+        let started = Instant::now();
+        assert_eq!(queue.pop_batch_timeout(2, Duration::from_secs(5)), Ok(vec![1, 2]));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        pusher.join().unwrap();
+        assert_eq!(queue.pop_batch_timeout(2, Duration::from_millis(10)), Ok(vec![3]));
+        assert_eq!(queue.pop_batch_timeout(2, Duration::from_millis(10)), Err(PopError::Timeout));
+    }
+
+    #[test]
+    fn closed_queue_drains_before_reporting_closed() {
+        let queue = BlockingQueue::new(16, OverflowPolicy::DropNewest);
+        assert!(queue.push_slice(&[1, 2, 3]));
+        queue.close();
+        assert!(!queue.push_slice(&[4]));
+        assert_eq!(queue.pop_batch_timeout(16, Duration::from_secs(5)), Ok(vec![1, 2, 3]));
+        assert_eq!(queue.pop_batch_timeout(16, Duration::from_secs(5)), Err(PopError::Closed));
+
+        // A consumer already waiting is woken by the close
+        let queue = BlockingQueue::<i32>::new(16, OverflowPolicy::DropNewest);
+        let waiter = {
+            let queue = Arc::clone(&queue);
+            std::thread::spawn(move || queue.pop_batch_timeout(16, Duration::from_secs(5)))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        queue.close();
+        assert_eq!(waiter.join().unwrap(), Err(PopError::Closed));
+    }
+
+    /// Everything a queue hands out, with gaps spelled out as `None`.
+    fn drain_with_gaps(queue: &BlockingQueue<i32>) -> Vec<Option<i32>> {
+        let mut out = Vec::new();
+        let mut items = Vec::new();
+        while let Ok(gap) = queue.pop_into_timeout(&mut items, usize::MAX, Duration::ZERO) {
+            out.extend(std::iter::repeat_n(None, gap));
+            out.extend(items.iter().copied().map(Some));
+        }
+        out
+    }
+
+    #[test]
+    fn pop_into_timeout_reuses_the_buffer() {
+        let queue = BlockingQueue::new(8, OverflowPolicy::DropNewest);
+        let mut items = Vec::with_capacity(4);
+        let storage = items.as_ptr();
+        for batch in [[1, 2, 3, 4], [5, 6, 7, 8]] {
+            assert!(queue.push_slice(&batch));
+            assert_eq!(queue.pop_into_timeout(&mut items, 4, Duration::ZERO), Ok(0));
+            assert_eq!(items, batch);
+            assert_eq!(items.as_ptr(), storage);
+        }
+        assert_eq!(queue.pop_into_timeout(&mut items, 4, Duration::ZERO), Err(PopError::Timeout));
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn drop_newest_leaves_a_gap_after_the_queued_items() {
+        let queue = BlockingQueue::new(4, OverflowPolicy::DropNewest);
+        assert!(queue.push_slice(&[1, 2, 3]));
+        assert!(!queue.push_slice(&[4, 5]));
+        assert!(!queue.push_slice(&[6, 7]));
+        assert!(queue.push_slice(&[8]));
+        assert_eq!(queue.dropped(), 4);
+        assert_eq!(drain_with_gaps(&queue), [Some(1), Some(2), Some(3), None, None, None, None, Some(8)]);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_items() {
+        let queue = BlockingQueue::new(4, OverflowPolicy::DropOldest);
+        assert!(queue.push_slice(&[1, 2, 3]));
+        assert!(queue.push_slice(&[4, 5]));
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(drain_with_gaps(&queue), [None, Some(2), Some(3), Some(4), Some(5)]);
+
+        // A batch larger than the queue keeps only its tail
+        assert!(queue.push_slice(&[6]));
+        assert!(queue.push_slice(&[7, 8, 9, 10, 11]));
+        assert_eq!(queue.dropped(), 3);
+        assert_eq!(drain_with_gaps(&queue), [None, None, Some(8), Some(9), Some(10), Some(11)]);
+
+        // Consumers that don't need the timeline just skip the gaps
+        assert!(queue.push_slice(&[1, 2, 3, 4, 5]));
+        assert_eq!(queue.pop_batch_timeout(usize::MAX, Duration::ZERO), Ok(vec![2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn closed_queue_counts_late_pushes_apart_from_overflow() {
+        let queue = BlockingQueue::new(2, OverflowPolicy::BlockWithTimeout(Duration::from_secs(10)));
+        assert!(queue.push_slice(&[1, 2]));
+        drop(CloseOnDrop(Arc::clone(&queue)));
+        // Closed: rejected at once instead of waiting for room
+        let started = Instant::now();
+        assert!(!queue.push_slice(&[3, 4, 5]));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(queue.dropped(), 0);
+        assert_eq!(queue.discarded(), 3);
+        assert_eq!(queue.pop_batch_timeout(8, Duration::ZERO), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn block_with_timeout_waits_for_room() {
+        let queue = BlockingQueue::new(4, OverflowPolicy::BlockWithTimeout(Duration::from_secs(5)));
+        assert!(queue.push_slice(&[1, 2, 3]));
+        let consumer = {
+            let queue = Arc::clone(&queue);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                queue.pop_batch_timeout(2, Duration::ZERO)
+            })
+        };
+        assert!(queue.push_slice(&[4, 5]));
+        assert_eq!(consumer.join().unwrap(), Ok(vec![1, 2]));
+        assert_eq!(queue.dropped(), 0);
+
+        // Nobody makes room: the batch is dropped once the timeout runs out
+        let queue = BlockingQueue::new(2, OverflowPolicy::BlockWithTimeout(Duration::from_millis(10)));
+        assert!(queue.push_slice(&[1, 2]));
+        assert!(!queue.push_slice(&[3]));
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(drain_with_gaps(&queue), [Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn capture_ring_keeps_order_across_threads() {
+        let ring = CaptureRing::new(64);
+        let mut producer = ring.producer().unwrap();
+        let mut consumer = ring.consumer().unwrap();
+
+        let writer = std::thread::spawn(move || {
+            for start in (0..10_000).step_by(10) {
+                while !producer.push_iter((start..start + 10).map(|i| i as f32)) {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut received = Vec::new();
+        let mut batch = Vec::new();
+        while received.len() < 10_000 {
+            if consumer.pop_into(&mut batch, 32).is_some() {
+                received.extend_from_slice(&batch);
+            } else {
+                std::thread::yield_now();
+            }
+        }
+        writer.join().unwrap();
+        assert!(received.iter().enumerate().all(|(i, &s)| s == i as f32));
+    }
+
+    #[test]
+    fn capture_ring_drops_whole_batches_and_counts_them() {
+        let ring = CaptureRing::new(8);
+        let mut producer = ring.producer().unwrap();
+        assert!(producer.push_slice(&[1.0; 6]));
+        assert!(!producer.push_slice(&[2.0; 4]));
+        assert!(!producer.push_slice(&[3.0; 3]));
+        assert!(producer.push_slice(&[4.0; 2]));
+        assert_eq!(ring.overruns(), 7);
+        assert_eq!(ring.captured(), 8);
+
+        ring.close();
+        let mut consumer = ring.consumer().unwrap();
+        let mut out = Vec::new();
+        let timeout = Duration::from_secs(5);
+        // The dropped batches come back as one gap between the batches around them
+        assert_eq!(consumer.pop_into_timeout(&mut out, usize::MAX, timeout), Ok(0));
+        assert_eq!(out, [1.0; 6]);
+        assert_eq!(consumer.pop_into_timeout(&mut out, usize::MAX, timeout), Ok(7));
+        assert_eq!(out, [4.0; 2]);
+        assert_eq!(consumer.pop_into_timeout(&mut out, usize::MAX, timeout), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn capture_ring_wakes_a_waiting_consumer() {
+        let ring = CaptureRing::new(8);
+        let mut producer = ring.producer().unwrap();
+        let mut consumer = ring.consumer().unwrap();
+        let waiter = std::thread::spawn(move || {
+            let mut out = Vec::new();
+            let timeout = Duration::from_secs(10);
+            let started = Instant::now();
+            let first = consumer.pop_into_timeout(&mut out, usize::MAX, timeout).map(|_| out.clone());
+            let second = consumer.pop_into_timeout(&mut out, usize::MAX, timeout);
+            (first, second, started.elapsed())
+        });
+
+        // Both the push and the close end the wait, long before the timeout
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(producer.push_slice(&[1.0, 2.0]));
+        std::thread::sleep(Duration::from_millis(50));
+        ring.close();
+        let (first, second, elapsed) = waiter.join().unwrap();
+        assert_eq!(first, Ok(vec![1.0, 2.0]));
+        assert_eq!(second, Err(PopError::Closed));
+        assert!(elapsed < Duration::from_secs(5), "waited {:?}", elapsed);
+    }
+
+    #[test]
+    fn capture_ring_producer_is_handed_back_on_drop() {
+        let ring = CaptureRing::new(8);
+        let producer = ring.producer().unwrap();
+        assert!(ring.has_producer());
+        assert!(ring.producer().is_err());
+        drop(producer);
+        assert!(!ring.has_producer());
+        assert!(ring.producer().is_ok());
+    }
 }
@@ -0,0 +1,203 @@
+//! Fan-out of a track's processed audio to any number of consumers.
+//!
+//! The resampler publishes two streams, which differ once noise suppression
+//! or the VAD gate are on: the full recording timeline and the audio meant
+//! for speech recognition.  Each consumer subscribes to one of them with a
+//! queue of its own, so a slow consumer with a dropping policy only
+//! overflows its own queue.  A `BlockWithTimeout` queue is the exception:
+//! publishing waits for it, holding up the resampler and every subscriber
+//! after it.
+//!
+//! A consumer of either stream, like another writer or recognizer, only
+//! needs to subscribe.  The level meter, noise suppression and the VAD are
+//! not subscribers: the meter reads the signal before the limiter, and the
+//! other two decide what the taps carry, so they stay stages of
+//! `TrackProcessor`.
+
+use std::sync::{Arc, RwLock};
+
+use crate::buffers::{BlockingQueue, OverflowPolicy};
+
+/// The processed stream a subscriber receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tap {
+    /// What the WAV file gets: the whole timeline, unless `vad_drop_silence`
+    Recording,
+    /// What the recognizers get: only speech while the VAD gate is on
+    Recognition,
+}
+
+#[derive(Clone)]
+struct Subscriber {
+    name: String,
+    tap: Tap,
+    queue: Arc<BlockingQueue<f32>>,
+}
+
+#[derive(Default)]
+struct BusState {
+    /// Replaced, never modified, so `publish` can push to a snapshot
+    /// without holding the lock
+    subscribers: Arc<Vec<Subscriber>>,
+    closed: bool,
+}
+
+pub struct AudioBus {
+    state: RwLock<BusState>,
+}
+
+impl AudioBus {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self { state: RwLock::new(BusState::default()) })
+    }
+
+    /// Add a consumer of `tap`.  It receives what is published from now on,
+    /// through a queue of `capacity` samples overflowing by `policy`; on a
+    /// closed bus the queue starts out closed.
+    pub fn subscribe(&self, name: &str, tap: Tap, capacity: usize, policy: OverflowPolicy) -> Arc<BlockingQueue<f32>> {
+        let queue = BlockingQueue::new(capacity, policy);
+        let mut state = self.state.write().unwrap();
+        if state.closed {
+            queue.close();
+        }
+        let mut subscribers = Vec::clone(&state.subscribers);
+        subscribers.push(Subscriber { name: name.to_string(), tap, queue: Arc::clone(&queue) });
+        state.subscribers = Arc::new(subscribers);
+        queue
+    }
+
+    /// Copy `samples` into the queue of every subscriber of `tap`, in
+    /// subscription order.  A full `BlockWithTimeout` queue makes this wait
+    /// up to its timeout before the subscribers after it get the samples.
+    /// Rejected samples are counted by the queue, not logged here.
+    pub fn publish(&self, tap: Tap, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
+        }
+        // Don't hold the lock while a blocking queue waits
+        let subscribers = Arc::clone(&self.state.read().unwrap().subscribers);
+        for subscriber in subscribers.iter().filter(|s| s.tap == tap) {
+            if !subscriber.queue.push_slice(samples) {
+                log::debug!("Resampler: Failed to push to the {} queue", subscriber.name);
+            }
+        }
+    }
+
+    /// Close every subscriber's queue; they drain what is left, then report
+    /// `PopError::Closed`.
+    pub fn close(&self) {
+        let mut state = self.state.write().unwrap();
+        state.closed = true;
+        for subscriber in state.subscribers.iter() {
+            subscriber.queue.close();
+        }
+    }
+
+    /// Samples each subscriber's queue has dropped, in subscription order.
+    pub fn dropped(&self) -> Vec<(String, u64)> {
+        let state = self.state.read().unwrap();
+        state.subscribers.iter().map(|s| (s.name.clone(), s.queue.dropped())).collect()
+    }
+
+    /// Samples published to each subscriber after its queue was closed,
+    /// in subscription order.
+    pub fn discarded(&self) -> Vec<(String, u64)> {
+        let state = self.state.read().unwrap();
+        state.subscribers.iter().map(|s| (s.name.clone(), s.queue.discarded())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::PopError;
+    use std::time::Duration;
+
+    fn drain(queue: &BlockingQueue<f32>) -> Vec<f32> {
+        let mut out = Vec::new();
+        while let Ok(batch) = queue.pop_batch_timeout(usize::MAX, Duration::ZERO) {
+            out.extend(batch);
+        }
+        out
+    }
+
+    #[test]
+    fn subscribers_get_their_tap_through_their_own_queue() {
+        let bus = AudioBus::new();
+        let wav = bus.subscribe("wav", Tap::Recording, 16, OverflowPolicy::DropNewest);
+        let streamer = bus.subscribe("streamer", Tap::Recording, 4, OverflowPolicy::DropOldest);
+        let recognizer = bus.subscribe("realtime", Tap::Recognition, 16, OverflowPolicy::DropNewest);
+
+        bus.publish(Tap::Recording, &[1.0, 2.0, 3.0]);
+        bus.publish(Tap::Recognition, &[9.0]);
+        bus.publish(Tap::Recording, &[4.0, 5.0]);
+        bus.close();
+
+        assert_eq!(drain(&wav), vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        // The small queue lost its oldest sample without affecting the others
+        assert_eq!(drain(&streamer), vec![2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(drain(&recognizer), vec![9.0]);
+        assert_eq!(wav.pop_batch_timeout(1, Duration::ZERO), Err(PopError::Closed));
+        assert_eq!(
+            bus.dropped(),
+            vec![("wav".to_string(), 0), ("streamer".to_string(), 1), ("realtime".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn late_subscribers_start_from_the_next_publish() {
+        let bus = AudioBus::new();
+        bus.publish(Tap::Recording, &[1.0]);
+        let meter = bus.subscribe("meter", Tap::Recording, 16, OverflowPolicy::DropNewest);
+        bus.publish(Tap::Recording, &[2.0]);
+        bus.close();
+        assert_eq!(drain(&meter), vec![2.0]);
+
+        let after_close = bus.subscribe("vad", Tap::Recognition, 16, OverflowPolicy::DropNewest);
+        assert_eq!(after_close.pop_batch_timeout(1, Duration::ZERO), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn blocked_publish_does_not_hold_up_the_bus() {
+        let bus = AudioBus::new();
+        let stalled = bus.subscribe("stalled", Tap::Recording, 1, OverflowPolicy::BlockWithTimeout(Duration::from_secs(2)));
+        bus.publish(Tap::Recording, &[1.0]);
+        let publisher = {
+            let bus = Arc::clone(&bus);
+            std::thread::spawn(move || bus.publish(Tap::Recording, &[2.0]))
+        };
+        std::thread::sleep(Duration::from_millis(100));
+
+        // While the publish waits for room, the bus can still be inspected,
+        // subscribed to and closed
+        let started = std::time::Instant::now();
+        assert_eq!(bus.dropped(), vec![("stalled".to_string(), 0)]);
+        let late = bus.subscribe("late", Tap::Recording, 16, OverflowPolicy::DropNewest);
+        bus.close();
+        assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+        publisher.join().unwrap();
+
+        assert_eq!(drain(&stalled), vec![1.0]);
+        assert_eq!(drain(&late), Vec::<f32>::new());
+    }
+
+    #[test]
+    fn blocking_subscriber_delays_publish_for_everyone() {
+        let block = Duration::from_millis(200);
+        let bus = AudioBus::new();
+        let stalled = bus.subscribe("stalled", Tap::Recording, 2, OverflowPolicy::BlockWithTimeout(block));
+        let wav = bus.subscribe("wav", Tap::Recording, 16, OverflowPolicy::DropNewest);
+
+        bus.publish(Tap::Recording, &[1.0, 2.0]);
+        // The stalled queue is full: publishing waits out its timeout, then
+        // drops for it alone
+        let started = std::time::Instant::now();
+        bus.publish(Tap::Recording, &[3.0]);
+        assert!(started.elapsed() >= block, "returned after {:?}", started.elapsed());
+        bus.close();
+
+        assert_eq!(drain(&stalled), vec![1.0, 2.0]);
+        assert_eq!(drain(&wav), vec![1.0, 2.0, 3.0]);
+        assert_eq!(bus.dropped(), vec![("stalled".to_string(), 1), ("wav".to_string(), 0)]);
+    }
+}
@@ -0,0 +1,171 @@
+//! `pstt calibrate`: find a good `audio_gain` from a short test recording.
+//!
+//! The passage is recorded through the same mix → DSP → gain → resample path
+//! as a real recording, then run through the same level analysis.
+//! Level-dependent stages that would hide the problem are switched off for
+//! the measurement: automatic gain, noise suppression and the VAD gate, and
+//! the limiter ceiling is raised to full scale so clipping still shows.
+
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use crate::analysis::MAX_GAIN;
+use crate::buffers::{AudioPipeline, BlockingQueue};
+use crate::bus::Tap;
+use crate::capture::{CaptureSupervisor, RecoverySettings};
+use crate::config::{Config, InputProcessing};
+use crate::resampler::{self, TrackInfo};
+use crate::source::AudioSource;
+use crate::status_line;
+
+/// The opening of "The Rainbow Passage", a standard text for voice
+/// recordings: it covers most English speech sounds in a few sentences.
+pub const PASSAGE: &str = "\
+When the sunlight strikes raindrops in the air, they act as a prism and \
+form a rainbow. The rainbow is a division of white light into many \
+beautiful colors. These take the shape of a long round arch, with its \
+path high above, and its two ends apparently beyond the horizon. There \
+is, according to legend, a boiling pot of gold at one end. People look, \
+but no one ever finds it. When a man looks for something beyond his \
+reach, his friends say he is looking for the pot of gold at the end of \
+the rainbow.";
+
+/// The recording settings with everything that adapts to the level turned off.
+pub fn measurement_config(config: &Config) -> Config {
+    let mut cfg = config.clone();
+    cfg.gain_mode = "fixed".to_string();
+    cfg.limiter_threshold_db = 0.0;
+    cfg.vad_enabled = false;
+    cfg.noise_suppression = "off".to_string();
+    cfg
+}
+
+/// Record up to `seconds` from `source`, mixed and processed by
+/// `processing`, and return the samples a recording would write to its WAV
+/// file.  Stops early when `running` is cleared or a finite source ends.
+pub fn record(
+    source: Box<dyn AudioSource>,
+    processing: &InputProcessing,
+    config: &Config,
+    seconds: f32,
+    running: &AtomicBool,
+) -> Result<Vec<f32>> {
+    let mut config = measurement_config(config);
+    // The WAV queue is only drained at the end, so it must hold everything
+    config.buffer_seconds = seconds.max(0.0) + 5.0;
+    let config = Arc::new(config);
+    let format = source.format();
+
+    let pipeline = AudioPipeline::new(format, &config);
+    // Only the recording tap is measured; nothing listens for recognition
+    let wav = pipeline.subscribe("WAV", Tap::Recording, &config.writer_overflow);
+    // Transcript markers (device lost/restored) have nowhere to go here
+    let (text_tx, _text_rx) = mpsc::channel();
+    let capture = CaptureSupervisor::start(
+        source,
+        Arc::clone(&pipeline.raw_queue),
+        text_tx,
+        None,
+        RecoverySettings::default(),
+    )
+    .context("Failed to start recording")?;
+
+    let resampler_handle = {
+        let raw = Arc::clone(&pipeline.raw_queue);
+        let bus = Arc::clone(&pipeline.bus);
+        let cfg = Arc::clone(&config);
+        let track = TrackInfo {
+            format,
+            label: None,
+            processing: processing.clone(),
+            speech_segments_path: None,
+            audio_stats_path: None,
+        };
+        std::thread::spawn(move || resampler::resampler_thread(raw, bus, track, cfg))
+    };
+
+    status_line::begin();
+    let started = Instant::now();
+    while running.load(Ordering::Relaxed)
+        && started.elapsed().as_secs_f32() < seconds
+        && !capture.is_finished()
+    {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    status_line::end();
+
+    // Capture first: stopping it closes the raw queue, so the resampler
+    // drains everything it delivered
+    capture.stop();
+    let _ = resampler_handle.join();
+
+    Ok(drain(&wav))
+}
+
+fn drain(queue: &BlockingQueue<f32>) -> Vec<f32> {
+    let mut samples = Vec::new();
+    while let Some(batch) = queue.try_pop_batch(usize::MAX) {
+        samples.extend(batch);
+    }
+    samples
+}
+
+/// Round to the one decimal written to config.toml, within the accepted range.
+pub fn config_gain(recommended: f32) -> f32 {
+    ((recommended * 10.0).round() / 10.0).clamp(0.1, MAX_GAIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{Signal, SourceFormat, SyntheticSource};
+    use crate::analysis::analyze;
+    use crate::config::fixture::parse_with;
+
+    fn test_config() -> Config {
+        let mut config = parse_with(r#"
+            gain_mode = "auto"
+            vad_enabled = true
+            noise_suppression = "both"
+        "#);
+        config.audio_gain = 2.0;
+        config
+    }
+
+    #[test]
+    fn measures_at_the_configured_fixed_gain() {
+        let cfg = measurement_config(&test_config());
+        assert_eq!(cfg.gain_mode, "fixed");
+        assert!(!cfg.vad_enabled);
+        assert_eq!(cfg.noise_suppression, "off");
+        assert_eq!(cfg.audio_gain, 2.0);
+    }
+
+    #[test]
+    fn quiet_recording_recommends_more_gain() {
+        let signal = Signal::Tone { frequency: 440.0, amplitude: 0.01 };
+        let format = SourceFormat { sample_rate: 48000, channels: 1 };
+        let source = SyntheticSource::new(signal, format, Some(Duration::from_secs(1)), false);
+        let running = AtomicBool::new(true);
+
+        let samples = record(Box::new(source), &InputProcessing::default(), &test_config(), 5.0, &running).unwrap();
+        // Resampler edges aside, the whole second arrives at 16 kHz
+        assert!(samples.len().abs_diff(16000) < 400, "{} samples", samples.len());
+
+        // 0.01 at 2x is far below the target level
+        let recommended = analyze(&samples, 16000, 2.0).recommended_gain;
+        assert!(recommended > 8.0, "recommended {}", recommended);
+        // and config.toml accepts it as is
+        assert!((config_gain(recommended) - recommended).abs() <= 0.05, "recommended {}", recommended);
+    }
+
+    #[test]
+    fn config_gain_rounds_into_the_accepted_range() {
+        assert_eq!(config_gain(3.24), 3.2);
+        assert_eq!(config_gain(17.0), 17.0);
+        assert_eq!(config_gain(25.0), MAX_GAIN);
+        assert_eq!(config_gain(0.01), 0.1);
+    }
+}
@@ -0,0 +1,490 @@
+//! Capture supervision: notices when the input stream dies and reopens it.
+//!
+//! A stream is considered lost when its error callback reports that the
+//! device is gone, or when no data callback has arrived for
+//! `RecoverySettings::stall_timeout`.  While the device is lost the
+//! supervisor pushes silence into the raw queue so the WAV timeline stays
+//! intact, and marks the gap with `[device lost]` / `[device restored]`
+//! lines in the real-time transcript.  Only sources that report themselves
+//! `recoverable` (live devices) are watched this way: files, stdin and
+//! generated signals may pause for as long as they like, and mark
+//! themselves finished once they end, which ends supervision.
+
+use anyhow::Result;
+use chrono::Local;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::buffers::CaptureRing;
+use crate::recognition::{source_prefix, RecognizedText};
+use crate::source::AudioSource;
+use crate::status_line;
+
+/// Liveness signals shared between a capture callback and its supervisor.
+pub struct CaptureHealth {
+    failed: AtomicBool,
+    finished: AtomicBool,
+    last_callback_ms: AtomicU64,
+    epoch: Instant,
+}
+
+impl CaptureHealth {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            failed: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            last_callback_ms: AtomicU64::new(0),
+            epoch: Instant::now(),
+        })
+    }
+
+    /// Called from the data callback every time samples arrive.
+    pub fn mark_callback(&self) {
+        let ms = self.epoch.elapsed().as_millis() as u64;
+        self.last_callback_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// Called from the error callback when the device has gone away.
+    pub fn mark_failed(&self) {
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    /// Called by finite sources once their last sample has been pushed.
+    pub fn mark_finished(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    fn last_callback(&self) -> Instant {
+        self.epoch + Duration::from_millis(self.last_callback_ms.load(Ordering::Relaxed))
+    }
+
+    /// Forget a previous failure and restart the stall timer.
+    fn reset(&self) {
+        self.failed.store(false, Ordering::Relaxed);
+        self.mark_callback();
+    }
+}
+
+/// Timing knobs for loss detection and reconnection.
+#[derive(Debug, Clone)]
+pub struct RecoverySettings {
+    /// How long without a data callback before the device counts as lost.
+    pub stall_timeout: Duration,
+    /// Delay between reopen attempts while the device is lost.
+    pub retry_interval: Duration,
+    /// How often the supervisor checks stream health.
+    pub poll_interval: Duration,
+}
+
+impl Default for RecoverySettings {
+    fn default() -> Self {
+        Self {
+            stall_timeout: Duration::from_secs(2),
+            retry_interval: Duration::from_secs(1),
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Owns the running capture and the watchdog thread that keeps it alive.
+pub struct CaptureSupervisor {
+    stop: Arc<AtomicBool>,
+    health: Arc<CaptureHealth>,
+    raw_queue: Arc<CaptureRing>,
+    handle: JoinHandle<()>,
+}
+
+/// How long `CaptureSupervisor::stop` waits for a source's producer thread
+/// to let go of the raw queue.  Only a reader blocked on stdin gets near it.
+const PRODUCER_RELEASE_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl CaptureSupervisor {
+    /// Open `source` and start watching it.  The first open happens on the
+    /// calling thread so startup errors are returned directly.  `label`
+    /// tags the transcript markers when several inputs are recorded.
+    pub fn start(
+        mut source: Box<dyn AudioSource>,
+        raw_queue: Arc<CaptureRing>,
+        text_sender: mpsc::Sender<RecognizedText>,
+        label: Option<String>,
+        settings: RecoverySettings,
+    ) -> Result<Self> {
+        let health = CaptureHealth::new();
+        health.reset();
+        let guard = source.open(&raw_queue, &health)?;
+        let format = source.format();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            let health = Arc::clone(&health);
+            let raw_queue = Arc::clone(&raw_queue);
+            std::thread::spawn(move || {
+                let mut watchdog = Watchdog {
+                    source,
+                    raw_queue,
+                    health,
+                    text_sender,
+                    label,
+                    sample_rate: format.sample_rate,
+                    channels: format.channels.max(1) as usize,
+                    settings,
+                };
+                watchdog.run(guard, &stop);
+                log::info!("Capture supervisor exiting");
+            })
+        };
+
+        Ok(Self { stop, health, raw_queue, handle })
+    }
+
+    /// True once a finite source has delivered all of its audio.
+    pub fn is_finished(&self) -> bool {
+        self.health.is_finished()
+    }
+
+    /// Stop capture, wait until nothing can push into the raw queue any
+    /// more, then close it so the resampler drains it and finishes.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+
+        // Producer threads of file/stdin/synthetic sources exit on their own
+        // shortly after their guard is dropped
+        let deadline = Instant::now() + PRODUCER_RELEASE_TIMEOUT;
+        while self.raw_queue.has_producer() {
+            if Instant::now() >= deadline {
+                log::warn!("Capture: input still running at stop, its last samples may be lost");
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        self.raw_queue.close();
+        log::info!(
+            "Capture stopped: {} samples captured, {} dropped",
+            self.raw_queue.captured(),
+            self.raw_queue.overruns()
+        );
+    }
+}
+
+struct Watchdog {
+    source: Box<dyn AudioSource>,
+    raw_queue: Arc<CaptureRing>,
+    health: Arc<CaptureHealth>,
+    text_sender: mpsc::Sender<RecognizedText>,
+    label: Option<String>,
+    sample_rate: u32,
+    channels: usize,
+    settings: RecoverySettings,
+}
+
+impl Watchdog {
+    fn run(&mut self, guard: Box<dyn Send>, stop: &AtomicBool) {
+        let mut guard = Some(guard);
+        // When lost: the moment audio stopped and how many silent frames have been filled since
+        let mut lost_since = Instant::now();
+        let mut silence_frames: u64 = 0;
+        let mut next_retry = Instant::now();
+        let mut overruns: u64 = 0;
+        let recoverable = self.source.recoverable();
+
+        while !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(self.settings.poll_interval);
+            overruns = self.report_overruns(overruns);
+
+            if self.health.is_finished() {
+                log::info!("Input finished: {}", self.source.name());
+                break;
+            }
+
+            if guard.is_some() {
+                let stalled = self.health.last_callback().elapsed() > self.settings.stall_timeout;
+                if recoverable && (self.health.has_failed() || stalled) {
+                    log::warn!(
+                        "Input device lost: {} ({})",
+                        self.source.name(),
+                        if stalled { "no audio callbacks" } else { "stream error" }
+                    );
+                    self.mark_transcript("[device lost]");
+                    guard = None;
+                    lost_since = self.health.last_callback();
+                    silence_frames = 0;
+                    next_retry = Instant::now();
+                }
+                continue;
+            }
+
+            silence_frames = self.fill_silence(lost_since, silence_frames);
+
+            if Instant::now() >= next_retry {
+                self.health.reset();
+                match self.source.open(&self.raw_queue, &self.health) {
+                    Ok(g) => {
+                        log::info!(
+                            "Input device restored: {} (gap: {:.1}s)",
+                            self.source.name(),
+                            silence_frames as f64 / self.sample_rate as f64
+                        );
+                        self.mark_transcript("[device restored]");
+                        guard = Some(g);
+                    }
+                    Err(e) => {
+                        log::debug!("Reopening input device failed: {}", e);
+                        next_retry = Instant::now() + self.settings.retry_interval;
+                    }
+                }
+            }
+        }
+
+        // Pad up to the stop time so the recording keeps its full length
+        if guard.is_none() {
+            self.fill_silence(lost_since, silence_frames);
+        }
+        self.report_overruns(overruns);
+    }
+
+    /// Push silence covering `lost_since..now`; returns the total frames filled.
+    /// While the lost stream still holds the ring's producer nothing is
+    /// pushed, and the next poll catches up.
+    fn fill_silence(&self, lost_since: Instant, filled: u64) -> u64 {
+        let due = (lost_since.elapsed().as_secs_f64() * self.sample_rate as f64) as u64;
+        if due <= filled {
+            return filled;
+        }
+        let Ok(mut producer) = self.raw_queue.producer() else {
+            return filled;
+        };
+        let samples = (due - filled) as usize * self.channels;
+        // A full ring counts the dropped silence as an overrun
+        producer.push_iter(std::iter::repeat_n(0.0, samples));
+        due
+    }
+
+    /// Log samples dropped by the raw queue since the last check; returns the
+    /// new total.  The audio callback only counts overruns, so they are
+    /// reported from here.
+    fn report_overruns(&self, reported: u64) -> u64 {
+        let total = self.raw_queue.overruns();
+        if total > reported {
+            log::warn!(
+                "Capture: raw queue overflow, dropped {} samples ({} total)",
+                total - reported,
+                total
+            );
+        }
+        total
+    }
+
+    fn mark_transcript(&self, marker: &str) {
+        status_line::print_line(&format!("⚠️  {}{}", source_prefix(self.label.as_deref()), marker));
+        let _ = self.text_sender.send(RecognizedText {
+            text: marker.to_string(),
+            timestamp: Local::now(),
+            is_final: true,
+            source: self.label.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    use crate::source::{PcmEncoding, RawPcmSource, SourceFormat};
+
+    /// Fake input that pushes 1.0-valued samples until told to fail.
+    struct FakeDevice {
+        /// Set to make the running capture die.
+        fail: Arc<AtomicBool>,
+        /// Report the failure via the error path (true) or just stall (false).
+        report_error: bool,
+        /// Number of upcoming `open` calls that should fail.
+        failing_opens: Arc<AtomicUsize>,
+        opens: Arc<AtomicUsize>,
+    }
+
+    struct FakeGuard(Arc<AtomicBool>);
+
+    impl Drop for FakeGuard {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    impl AudioSource for FakeDevice {
+        fn name(&self) -> String {
+            "fake".to_string()
+        }
+
+        fn format(&self) -> SourceFormat {
+            SourceFormat { sample_rate: 1000, channels: 1 }
+        }
+
+        fn open(
+            &mut self,
+            raw_queue: &Arc<CaptureRing>,
+            health: &Arc<CaptureHealth>,
+        ) -> Result<Box<dyn Send>> {
+            if self.failing_opens.load(Ordering::Relaxed) > 0 {
+                self.failing_opens.fetch_sub(1, Ordering::Relaxed);
+                anyhow::bail!("device unplugged");
+            }
+            let mut producer = raw_queue.producer()?;
+            self.opens.fetch_add(1, Ordering::Relaxed);
+            self.fail.store(false, Ordering::Relaxed);
+
+            let dropped = Arc::new(AtomicBool::new(false));
+            let (health, fail, done) = (
+                Arc::clone(health),
+                Arc::clone(&self.fail),
+                Arc::clone(&dropped),
+            );
+            let report_error = self.report_error;
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    if fail.load(Ordering::Relaxed) {
+                        if report_error {
+                            health.mark_failed();
+                        }
+                    } else {
+                        producer.push_slice(&[1.0; 10]);
+                        health.mark_callback();
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            });
+            Ok(Box::new(FakeGuard(dropped)))
+        }
+
+        fn recoverable(&self) -> bool {
+            true
+        }
+    }
+
+    /// A pipe whose writer pauses before every write.
+    struct SlowPipe {
+        writes: std::vec::IntoIter<Vec<u8>>,
+        pause: Duration,
+    }
+
+    impl std::io::Read for SlowPipe {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some(write) = self.writes.next() else {
+                return Ok(0);
+            };
+            std::thread::sleep(self.pause);
+            buf[..write.len()].copy_from_slice(&write);
+            Ok(write.len())
+        }
+    }
+
+    fn settings() -> RecoverySettings {
+        RecoverySettings {
+            stall_timeout: Duration::from_millis(100),
+            retry_interval: Duration::from_millis(30),
+            poll_interval: Duration::from_millis(10),
+        }
+    }
+
+    fn drain(queue: &Arc<CaptureRing>) -> Vec<f32> {
+        let mut out = Vec::new();
+        queue.consumer().unwrap().pop_into(&mut out, usize::MAX);
+        out
+    }
+
+    fn run_outage(report_error: bool) {
+        let fail = Arc::new(AtomicBool::new(false));
+        let failing_opens = Arc::new(AtomicUsize::new(0));
+        let opens = Arc::new(AtomicUsize::new(0));
+        let device = FakeDevice {
+            fail: Arc::clone(&fail),
+            report_error,
+            failing_opens: Arc::clone(&failing_opens),
+            opens: Arc::clone(&opens),
+        };
+        let raw_queue = CaptureRing::new(1 << 20);
+        let (tx, rx) = mpsc::channel();
+
+        let supervisor = CaptureSupervisor::start(
+            Box::new(device),
+            Arc::clone(&raw_queue),
+            tx,
+            Some("mic".to_string()),
+            settings(),
+        ).unwrap();
+
+        std::thread::sleep(Duration::from_millis(100));
+        // Unplug: the first two reconnect attempts fail too
+        failing_opens.store(2, Ordering::Relaxed);
+        fail.store(true, Ordering::Relaxed);
+
+        let lost = rx.recv_timeout(Duration::from_secs(2)).expect("no lost marker");
+        assert_eq!(lost.text, "[device lost]");
+        assert_eq!(lost.source.as_deref(), Some("mic"));
+        let restored = rx.recv_timeout(Duration::from_secs(2)).expect("no restored marker");
+        assert_eq!(restored.text, "[device restored]");
+        std::thread::sleep(Duration::from_millis(100));
+        supervisor.stop();
+
+        assert_eq!(opens.load(Ordering::Relaxed), 2, "initial open plus one successful reopen");
+        assert_eq!(failing_opens.load(Ordering::Relaxed), 0);
+
+        // Audio before the gap, silence during it, audio again afterwards
+        let samples = drain(&raw_queue);
+        let first_gap = samples.iter().position(|&s| s == 0.0).expect("no gap silence");
+        let resumed = samples[first_gap..].iter().position(|&s| s == 1.0).expect("audio did not resume");
+        assert!(first_gap > 0);
+        assert!(resumed > 0);
+    }
+
+    #[test]
+    fn recovers_from_stream_error() {
+        run_outage(true);
+    }
+
+    #[test]
+    fn recovers_from_missing_callbacks() {
+        run_outage(false);
+    }
+
+    #[test]
+    fn slow_pipe_is_not_a_lost_device() {
+        // Three writes of 10 frames, each after twice the stall timeout
+        let write = 16384i16.to_le_bytes().repeat(10);
+        let pipe = SlowPipe { writes: vec![write; 3].into_iter(), pause: settings().stall_timeout * 2 };
+        let format = SourceFormat { sample_rate: 1000, channels: 1 };
+        let source = RawPcmSource::new(Box::new(pipe), format, PcmEncoding::S16le);
+        let raw_queue = CaptureRing::new(1 << 10);
+        let (tx, rx) = mpsc::channel();
+
+        let supervisor = CaptureSupervisor::start(
+            Box::new(source),
+            Arc::clone(&raw_queue),
+            tx,
+            None,
+            settings(),
+        ).unwrap();
+        let started = Instant::now();
+        while !supervisor.is_finished() {
+            assert!(started.elapsed() < Duration::from_secs(5), "pipe never finished");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        supervisor.stop();
+
+        assert!(rx.try_recv().is_err(), "a pause in the pipe was reported as device loss");
+        assert_eq!(drain(&raw_queue), vec![0.5; 30]);
+    }
+}
@@ -2,14 +2,90 @@ use serde::Deserialize;
 use anyhow::{Result, Context};
 use std::fs;
 use std::path::Path;
+use crate::analysis::MAX_GAIN;
+
+/// Where `Config::load` reads the configuration from.
+pub const CONFIG_PATH: &str = "config.toml";
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     /// Audio sample rate for processing (Hz).  Defaults to 16000 when omitted.
     #[serde(default = "default_sample_rate")]
     pub sample_rate: u32,
+    /// Resampler used to reach `sample_rate`: "fast", "balanced" or "best".
+    /// Inputs already at `sample_rate` are passed through unchanged.
+    #[serde(default = "default_resampler_quality")]
+    pub resampler_quality: String,
+    /// Seconds of audio each pipeline queue holds before it overflows.
+    #[serde(default = "default_buffer_seconds")]
+    pub buffer_seconds: f32,
+    /// What the WAV writer's queue does when full: "drop_newest",
+    /// "drop_oldest" or "block_with_timeout".  Dropped audio is written as
+    /// silence so the recording keeps its timing.
+    #[serde(default = "default_writer_overflow")]
+    pub writer_overflow: String,
+    /// The same for the real-time recognizer's queue.
+    #[serde(default = "default_realtime_overflow")]
+    pub realtime_overflow: String,
+    /// How long "block_with_timeout" waits for room before dropping (ms).
+    #[serde(default = "default_overflow_block_ms")]
+    pub overflow_block_ms: u64,
+    /// Input gain.  With `gain_mode = "auto"` this is only the starting point.
     pub audio_gain: f32,
+    /// "fixed" applies `audio_gain` as is; "auto" adapts it to the input level.
+    #[serde(default = "default_gain_mode")]
+    pub gain_mode: String,
+    /// Output ceiling of the soft limiter after gain (dBFS).
+    #[serde(default = "default_limiter_threshold_db")]
+    pub limiter_threshold_db: f32,
+    /// How quickly the limiter's gain recovers after a peak (ms).
+    #[serde(default = "default_limiter_release_ms")]
+    pub limiter_release_ms: f32,
+    /// Show a live peak/RMS meter while recording.
+    #[serde(default = "default_level_meter")]
+    pub level_meter: bool,
+    /// Input device to record from, matched by 1-based index, exact name or
+    /// unique substring.  When omitted the device menu is shown at startup.
+    #[serde(default)]
+    pub input_device: Option<String>,
+    /// Channel selection and DSP chain of a single input.  With `[[inputs]]`
+    /// each input sets its own instead.
+    #[serde(flatten)]
+    pub processing: InputProcessing,
+    /// Several devices to record at once, each into its own track.
+    /// Mutually exclusive with `input_device`.
+    #[serde(default)]
+    pub inputs: Vec<InputConfig>,
     pub output_directory: String,
+    /// Voice activity detection: hold long silences back from the real-time
+    /// recognizer and write a `_speech.json` sidecar of speech segments.
+    #[serde(default)]
+    pub vad_enabled: bool,
+    /// How far above the running noise floor (dB) a frame must be to count as speech.
+    #[serde(default = "default_vad_threshold_db")]
+    pub vad_threshold_db: f32,
+    /// Pauses shorter than this stay inside one speech segment.
+    #[serde(default = "default_vad_hangover_ms")]
+    pub vad_hangover_ms: u32,
+    /// Silence still passed on after speech before the gate closes.
+    #[serde(default = "default_vad_max_silence_ms")]
+    pub vad_max_silence_ms: u32,
+    /// Also drop the gated-out silence from the WAV file.
+    #[serde(default)]
+    pub vad_drop_silence: bool,
+    /// Spectral noise suppression on the resampled stream: "off", or which
+    /// output gets the cleaned audio ("wav", "recognizer", "both").
+    #[serde(default = "default_noise_suppression")]
+    pub noise_suppression: String,
+    /// Where the noise profile comes from: "lead_in" (the first
+    /// `noise_learn_ms`) or "vad" (every frame the speech detector calls silent).
+    #[serde(default = "default_noise_learn")]
+    pub noise_learn: String,
+    #[serde(default = "default_noise_learn_ms")]
+    pub noise_learn_ms: u32,
+    /// Maximum attenuation of a noise-only frequency band (dB).
+    #[serde(default = "default_noise_reduction_db")]
+    pub noise_reduction_db: f32,
     #[serde(default)]
     pub vosk_model_path: Option<String>,
     pub whisper_model_path_accurate: String,
@@ -42,10 +118,298 @@ pub struct Config {
     pub ollama_timeout_secs: u64,
 }
 
+/// One device of a multi-input recording (`[[inputs]]` in config.toml).
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct InputConfig {
+    /// Short name used in transcript lines and file names, e.g. "mic".
+    pub label: String,
+    /// Device selector, same syntax as `input_device`.
+    pub device: String,
+    /// This device's channel selection and `[[inputs.dsp]]` chain.
+    #[serde(flatten)]
+    pub processing: InputProcessing,
+}
+
+/// How an input's channels are mixed down and processed before gain.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct InputProcessing {
+    /// Device channels (0-based) to average into the mono signal, e.g. `[2]`
+    /// for the third input.  Mutually exclusive with `channel_weights`.
+    #[serde(default)]
+    pub input_channels: Option<Vec<usize>>,
+    /// Per-channel mix weights (0-based order); channels without a weight
+    /// are dropped.  Mutually exclusive with `input_channels`.
+    #[serde(default)]
+    pub channel_weights: Option<Vec<f32>>,
+    /// Processing stages run in order between downmix and gain.
+    #[serde(default)]
+    pub dsp: Vec<DspStageConfig>,
+}
+
+impl InputProcessing {
+    /// Checks what can be checked without the device; its channel count is
+    /// checked at session start.
+    fn validate(&self) -> Result<()> {
+        for stage in &self.dsp {
+            stage.validate()?;
+        }
+        
+        if self.input_channels.is_some() && self.channel_weights.is_some() {
+            anyhow::bail!("Set either input_channels or channel_weights, not both");
+        }
+        if let Some(channels) = &self.input_channels {
+            if channels.is_empty() {
+                anyhow::bail!("input_channels must list at least one channel");
+            }
+        }
+        if let Some(weights) = &self.channel_weights {
+            if weights.is_empty() || weights.iter().all(|&w| w == 0.0) {
+                anyhow::bail!("channel_weights must contain at least one non-zero weight");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One stage of the `[[dsp]]` chain, selected by its `type` key.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DspStageConfig {
+    /// Removes DC offset (first-order high-pass around 20 Hz).
+    DcBlocker,
+    /// Second-order Butterworth high-pass against rumble and handling noise.
+    HighPass {
+        #[serde(default = "default_high_pass_hz")]
+        cutoff_hz: f32,
+    },
+    /// Removes mains hum at `frequency_hz` and its first `harmonics` multiples.
+    Notch {
+        #[serde(default = "default_notch_hz")]
+        frequency_hz: f32,
+        #[serde(default = "default_notch_q")]
+        q: f32,
+        #[serde(default = "default_notch_harmonics")]
+        harmonics: u32,
+    },
+    /// `y[n] = x[n] - coefficient * x[n-1]`, lifts consonants over low rumble.
+    PreEmphasis {
+        #[serde(default = "default_pre_emphasis")]
+        coefficient: f32,
+    },
+    Gain {
+        db: f32,
+    },
+    /// Look-ahead soft limiter, same as the one after `audio_gain`.
+    Limiter {
+        #[serde(default = "default_limiter_threshold_db")]
+        threshold_db: f32,
+        #[serde(default = "default_limiter_release_ms")]
+        release_ms: f32,
+    },
+    /// Mutes the signal while it stays below `threshold_db`.
+    NoiseGate {
+        #[serde(default = "default_gate_threshold_db")]
+        threshold_db: f32,
+        #[serde(default = "default_gate_hold_ms")]
+        hold_ms: f32,
+        #[serde(default = "default_gate_release_ms")]
+        release_ms: f32,
+    },
+}
+
+impl DspStageConfig {
+    fn validate(&self) -> Result<()> {
+        match *self {
+            DspStageConfig::DcBlocker => {}
+            DspStageConfig::HighPass { cutoff_hz } => {
+                if cutoff_hz <= 0.0 {
+                    anyhow::bail!("dsp high_pass: cutoff_hz must be positive");
+                }
+            }
+            DspStageConfig::Notch { frequency_hz, q, harmonics } => {
+                if frequency_hz <= 0.0 || q <= 0.0 || harmonics == 0 {
+                    anyhow::bail!("dsp notch: frequency_hz and q must be positive, harmonics at least 1");
+                }
+            }
+            DspStageConfig::PreEmphasis { coefficient } => {
+                if !(0.0..1.0).contains(&coefficient) {
+                    anyhow::bail!("dsp pre_emphasis: coefficient must be in 0.0..1.0 (typically 0.95-0.97)");
+                }
+            }
+            DspStageConfig::Gain { db } => {
+                if db.abs() > 40.0 {
+                    anyhow::bail!("dsp gain: db must be between -40 and 40");
+                }
+            }
+            DspStageConfig::Limiter { threshold_db, release_ms } => {
+                if !(-30.0..=0.0).contains(&threshold_db) || release_ms <= 0.0 {
+                    anyhow::bail!("dsp limiter: threshold_db must be between -30.0 and 0.0, release_ms positive");
+                }
+            }
+            DspStageConfig::NoiseGate { threshold_db, hold_ms, release_ms } => {
+                if threshold_db >= 0.0 || hold_ms < 0.0 || release_ms <= 0.0 {
+                    anyhow::bail!("dsp noise_gate: threshold_db must be negative, hold_ms >= 0, release_ms positive");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replace the value of the top-level `audio_gain = ...` line in config.toml
+/// text.  Comments, spacing and all other lines are kept exactly as they are.
+pub fn set_audio_gain(content: &str, gain: f32) -> Result<String> {
+    let mut out = String::with_capacity(content.len() + 8);
+    let mut replaced = false;
+    let mut in_table = false;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        in_table |= trimmed.starts_with('[');
+        let value_start = trimmed
+            .strip_prefix("audio_gain")
+            .map(|rest| rest.trim_start())
+            .filter(|rest| rest.starts_with('='));
+        match value_start {
+            Some(rest) if !in_table && !replaced => {
+                // Keep indentation, the `key =` spelling and any trailing comment
+                let value_at = line.len() - rest.len() + 1;
+                let after_eq = &line[value_at..];
+                let value_len = after_eq.find('#').unwrap_or(after_eq.trim_end_matches(['\r', '\n']).len());
+                let value = &after_eq[..value_len];
+                let padding = &value[..value.len() - value.trim_start().len()];
+                let trailing = &value[value.trim_end().len()..];
+                out.push_str(&line[..value_at]);
+                out.push_str(padding);
+                out.push_str(&format!("{:.1}", gain));
+                out.push_str(trailing);
+                out.push_str(&after_eq[value_len..]);
+                replaced = true;
+            }
+            _ => out.push_str(line),
+        }
+    }
+    if !replaced {
+        anyhow::bail!("No top-level audio_gain line found in {}", CONFIG_PATH);
+    }
+    Ok(out)
+}
+
+/// Check that input labels are usable in file names and distinct.
+pub fn validate_input_labels(labels: &[&str]) -> Result<()> {
+    for (i, label) in labels.iter().enumerate() {
+        if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            anyhow::bail!(
+                "Invalid input label \"{}\": use letters, digits, '-' or '_'",
+                label
+            );
+        }
+        if labels[..i].contains(label) {
+            anyhow::bail!("Input label \"{}\" is used more than once", label);
+        }
+    }
+    Ok(())
+}
+
 fn default_sample_rate() -> u32 {
     16000
 }
 
+fn default_resampler_quality() -> String {
+    "best".to_string()
+}
+
+fn default_buffer_seconds() -> f32 {
+    10.0
+}
+
+fn default_writer_overflow() -> String {
+    "drop_newest".to_string()
+}
+
+fn default_realtime_overflow() -> String {
+    "drop_oldest".to_string()
+}
+
+fn default_overflow_block_ms() -> u64 {
+    250
+}
+
+fn default_gain_mode() -> String {
+    "fixed".to_string()
+}
+
+fn default_high_pass_hz() -> f32 {
+    80.0
+}
+
+fn default_notch_hz() -> f32 {
+    50.0
+}
+
+fn default_notch_q() -> f32 {
+    30.0
+}
+
+fn default_notch_harmonics() -> u32 {
+    1
+}
+
+fn default_pre_emphasis() -> f32 {
+    0.97
+}
+
+fn default_gate_threshold_db() -> f32 {
+    -50.0
+}
+
+fn default_gate_hold_ms() -> f32 {
+    100.0
+}
+
+fn default_gate_release_ms() -> f32 {
+    50.0
+}
+
+fn default_limiter_threshold_db() -> f32 {
+    -1.0
+}
+
+fn default_limiter_release_ms() -> f32 {
+    100.0
+}
+
+fn default_level_meter() -> bool {
+    true
+}
+
+fn default_vad_threshold_db() -> f32 {
+    12.0
+}
+
+fn default_vad_hangover_ms() -> u32 {
+    300
+}
+
+fn default_vad_max_silence_ms() -> u32 {
+    1000
+}
+
+fn default_noise_suppression() -> String {
+    "off".to_string()
+}
+
+fn default_noise_learn() -> String {
+    "lead_in".to_string()
+}
+
+fn default_noise_learn_ms() -> u32 {
+    500
+}
+
+fn default_noise_reduction_db() -> f32 {
+    12.0
+}
+
 fn default_realtime_engine() -> String {
     "vosk".to_string()
 }
@@ -81,7 +445,7 @@ fn default_ollama_timeout_secs() -> u64 {
 
 impl Config {
     pub fn load() -> Result<Self> {
-        let config_path = "config.toml";
+        let config_path = CONFIG_PATH;
         
         if !Path::new(config_path).exists() {
             anyhow::bail!(
@@ -108,9 +472,104 @@ impl Config {
             anyhow::bail!("sample_rate must be between 8000 and 48000 Hz");
         }
         
+        if !matches!(self.resampler_quality.as_str(), "fast" | "balanced" | "best") {
+            anyhow::bail!(
+                "Unknown resampler_quality: \"{}\". Valid values: \"fast\", \"balanced\", \"best\"",
+                self.resampler_quality
+            );
+        }
+        
+        if !(1.0..=120.0).contains(&self.buffer_seconds) {
+            anyhow::bail!("buffer_seconds must be between 1 and 120");
+        }
+        for (key, policy) in [("writer_overflow", &self.writer_overflow), ("realtime_overflow", &self.realtime_overflow)] {
+            if !matches!(policy.as_str(), "drop_newest" | "drop_oldest" | "block_with_timeout") {
+                anyhow::bail!(
+                    "Unknown {}: \"{}\". Valid values: \"drop_newest\", \"drop_oldest\", \"block_with_timeout\"",
+                    key,
+                    policy
+                );
+            }
+        }
+        
         // Validate audio gain
-        if self.audio_gain <= 0.0 || self.audio_gain > 10.0 {
-            anyhow::bail!("audio_gain must be between 0.0 and 10.0 (recommended: 1.0-5.0)");
+        if self.audio_gain <= 0.0 || self.audio_gain > MAX_GAIN {
+            anyhow::bail!("audio_gain must be between 0.0 and {:.1} (recommended: 1.0-5.0)", MAX_GAIN);
+        }
+        
+        if self.gain_mode != "fixed" && self.gain_mode != "auto" {
+            anyhow::bail!(
+                "Unknown gain_mode: \"{}\". Valid values: \"fixed\", \"auto\"",
+                self.gain_mode
+            );
+        }
+        
+        if !(-30.0..=0.0).contains(&self.limiter_threshold_db) {
+            anyhow::bail!("limiter_threshold_db must be between -30.0 and 0.0 dBFS");
+        }
+        if self.limiter_release_ms <= 0.0 {
+            anyhow::bail!("limiter_release_ms must be positive");
+        }
+        
+        // Validate channel selection and DSP chain
+        self.processing.validate()?;
+        
+        // Validate multi-input recording: inputs differ in channel layout,
+        // so each one sets its own processing
+        if !self.inputs.is_empty() {
+            if self.input_device.is_some() {
+                anyhow::bail!("Set either input_device or [[inputs]], not both");
+            }
+            if self.processing != InputProcessing::default() {
+                anyhow::bail!(
+                    "With [[inputs]], set input_channels, channel_weights and [[inputs.dsp]] in each input, not at the top level"
+                );
+            }
+            let labels: Vec<&str> = self.inputs.iter().map(|i| i.label.as_str()).collect();
+            validate_input_labels(&labels)?;
+            for input in &self.inputs {
+                input.processing.validate().with_context(|| format!("Input \"{}\"", input.label))?;
+            }
+        }
+        
+        // Validate VAD settings
+        if self.vad_enabled {
+            if self.vad_threshold_db <= 0.0 {
+                anyhow::bail!("vad_threshold_db must be positive (recommended: 6-20)");
+            }
+            if self.vad_max_silence_ms < self.vad_hangover_ms {
+                anyhow::bail!("vad_max_silence_ms must be at least vad_hangover_ms");
+            }
+        }
+        
+        // Validate noise suppression
+        match self.noise_suppression.as_str() {
+            "off" | "both" => {}
+            "wav" | "recognizer" => {
+                // The gated stream is written as is, so both outputs must match
+                if self.vad_enabled && self.vad_drop_silence {
+                    anyhow::bail!(
+                        "vad_drop_silence writes the recognizer's audio to the WAV; \
+                         set noise_suppression to \"both\" or \"off\""
+                    );
+                }
+            }
+            other => anyhow::bail!(
+                "Unknown noise_suppression: \"{}\". Valid values: \"off\", \"wav\", \"recognizer\", \"both\"",
+                other
+            ),
+        }
+        if self.noise_learn != "lead_in" && self.noise_learn != "vad" {
+            anyhow::bail!(
+                "Unknown noise_learn: \"{}\". Valid values: \"lead_in\", \"vad\"",
+                self.noise_learn
+            );
+        }
+        if self.noise_learn == "lead_in" && self.noise_learn_ms == 0 {
+            anyhow::bail!("noise_learn_ms must be positive");
+        }
+        if self.noise_reduction_db <= 0.0 || self.noise_reduction_db > 40.0 {
+            anyhow::bail!("noise_reduction_db must be between 0 and 40 dB (recommended: 10-15)");
         }
         
         // Validate realtime_engine selection
@@ -188,6 +647,30 @@ impl Config {
     }
 }
 
+/// Config fixtures for the unit tests and the integration tests under
+/// `tests/`, so a new required key is added in one place.
+#[doc(hidden)]
+pub mod fixture {
+    use super::Config;
+
+    /// A minimal valid config followed by `extra`, which may end in tables.
+    pub fn toml_with(extra: &str) -> String {
+        format!(r#"
+            audio_gain = 1.0
+            output_directory = "./recordings"
+            realtime_engine = "vosk"
+            vosk_model_path = "./models/vosk"
+            whisper_model_path_accurate = "./models/ggml-small.en.bin"
+            enable_accurate_recognition = false
+            {}
+        "#, extra)
+    }
+
+    pub fn parse_with(extra: &str) -> Config {
+        toml::from_str(&toml_with(extra)).expect("parsing failed")
+    }
+}
+
 // -----------------------------------------------------------------------------
 // Unit tests
 // -----------------------------------------------------------------------------
@@ -195,7 +678,7 @@ impl Config {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use toml;
+    use super::fixture::{parse_with, toml_with};
 
     fn parse_toml(s: &str) -> Result<Config, toml::de::Error> {
         toml::from_str(s)
@@ -265,4 +748,203 @@ mod tests {
         assert_eq!(cfg.sample_rate, 16000);
         cfg.validate().unwrap();
     }
+
+    #[test]
+    fn input_device_is_optional() {
+        assert!(parse_with("").input_device.is_none());
+
+        let cfg = parse_with("input_device = \"USB Headset\"");
+        assert_eq!(cfg.input_device.as_deref(), Some("USB Headset"));
+    }
+
+    #[test]
+    fn channel_selection_options_are_exclusive() {
+        let cfg = parse_with("input_channels = [2]");
+        assert_eq!(cfg.processing.input_channels, Some(vec![2]));
+        cfg.validate().unwrap();
+
+        let cfg = parse_with("input_channels = [2]\nchannel_weights = [1.0]");
+        assert!(cfg.validate().unwrap_err().to_string().contains("not both"));
+
+        assert!(parse_with("input_channels = []").validate().is_err());
+    }
+
+    #[test]
+    fn inputs_need_distinct_file_safe_labels() {
+        let inputs = r#"
+            [[inputs]]
+            label = "mic"
+            device = "USB Headset"
+
+            [[inputs]]
+            label = "monitor"
+            device = "Monitor of"
+        "#;
+        let cfg = parse_with(inputs);
+        assert_eq!(cfg.inputs.len(), 2);
+        assert_eq!(
+            cfg.inputs[1],
+            InputConfig { label: "monitor".into(), device: "Monitor of".into(), processing: InputProcessing::default() }
+        );
+        cfg.validate().unwrap();
+
+        let cfg = parse_with(&format!("input_device = \"1\"\n{}", inputs));
+        assert!(cfg.validate().unwrap_err().to_string().contains("not both"));
+
+        assert!(validate_input_labels(&["mic", "mic"]).is_err());
+        assert!(validate_input_labels(&["my mic"]).is_err());
+        assert!(validate_input_labels(&[""]).is_err());
+        assert!(validate_input_labels(&["mic_1", "monitor-2"]).is_ok());
+    }
+
+    #[test]
+    fn each_input_sets_its_own_channels_and_dsp() {
+        let inputs = r#"
+            [[inputs]]
+            label = "mic"
+            device = "USB Headset"
+
+            [[inputs]]
+            label = "monitor"
+            device = "Monitor of"
+            channel_weights = [1.0, 1.0]
+
+            [[inputs.dsp]]
+            type = "high_pass"
+        "#;
+        let cfg = parse_with(inputs);
+        cfg.validate().unwrap();
+        assert_eq!(cfg.inputs[0].processing, InputProcessing::default());
+        assert_eq!(cfg.inputs[1].processing, InputProcessing {
+            input_channels: None,
+            channel_weights: Some(vec![1.0, 1.0]),
+            dsp: vec![DspStageConfig::HighPass { cutoff_hz: 80.0 }],
+        });
+
+        // A shared setting would apply one device's layout to the other
+        let cfg = parse_with(&format!("input_channels = [1]\n{}", inputs));
+        assert!(cfg.validate().unwrap_err().to_string().contains("each input"));
+
+        let bad = inputs.replace("channel_weights = [1.0, 1.0]", "channel_weights = []");
+        let err = parse_with(&bad).validate().unwrap_err();
+        assert!(format!("{:#}", err).contains("\"monitor\""), "{:#}", err);
+    }
+
+    #[test]
+    fn vad_defaults_and_validation() {
+        let cfg = parse_with("");
+        assert!(!cfg.vad_enabled);
+        assert!(!cfg.vad_drop_silence);
+        assert_eq!(cfg.vad_hangover_ms, 300);
+        assert_eq!(cfg.vad_max_silence_ms, 1000);
+
+        parse_with("vad_enabled = true").validate().unwrap();
+
+        let cfg = parse_with("vad_enabled = true\nvad_max_silence_ms = 100");
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn gain_mode_defaults_to_fixed() {
+        assert_eq!(parse_with("").gain_mode, "fixed");
+
+        parse_with("gain_mode = \"auto\"").validate().unwrap();
+
+        let cfg = parse_with("gain_mode = \"loud\"");
+        assert!(cfg.validate().unwrap_err().to_string().contains("gain_mode"));
+    }
+
+    #[test]
+    fn resampler_quality_defaults_to_best() {
+        assert_eq!(parse_with("").resampler_quality, "best");
+
+        for quality in ["fast", "balanced"] {
+            parse_with(&format!("resampler_quality = \"{}\"", quality)).validate().unwrap();
+        }
+
+        let cfg = parse_with("resampler_quality = \"ultra\"");
+        assert!(cfg.validate().unwrap_err().to_string().contains("resampler_quality"));
+    }
+
+    #[test]
+    fn queue_overflow_settings_default_and_validate() {
+        let cfg = parse_with("");
+        assert_eq!(cfg.buffer_seconds, 10.0);
+        assert_eq!(cfg.writer_overflow, "drop_newest");
+        assert_eq!(cfg.realtime_overflow, "drop_oldest");
+        cfg.validate().unwrap();
+
+        let cfg = parse_with("realtime_overflow = \"drop_everything\"");
+        assert!(cfg.validate().unwrap_err().to_string().contains("realtime_overflow"));
+        let cfg = parse_with("buffer_seconds = 0.5");
+        assert!(cfg.validate().unwrap_err().to_string().contains("buffer_seconds"));
+    }
+
+    #[test]
+    fn noise_suppression_defaults_off_and_validates_targets() {
+        let cfg = parse_with("");
+        assert_eq!(cfg.noise_suppression, "off");
+        assert_eq!(cfg.noise_learn, "lead_in");
+        assert_eq!(cfg.noise_learn_ms, 500);
+
+        parse_with("noise_suppression = \"recognizer\"\nnoise_learn = \"vad\"").validate().unwrap();
+
+        let cfg = parse_with("noise_suppression = \"loud\"");
+        assert!(cfg.validate().unwrap_err().to_string().contains("noise_suppression"));
+
+        // A gated WAV is the recognizer's stream, so it can't differ from it
+        let dropped = "vad_enabled = true\nvad_drop_silence = true\n";
+        assert!(parse_with(&format!("{}noise_suppression = \"wav\"", dropped)).validate().is_err());
+        parse_with(&format!("{}noise_suppression = \"both\"", dropped)).validate().unwrap();
+    }
+
+    #[test]
+    fn dsp_chain_parses_in_order_with_defaults() {
+        let chain = r#"
+            [[dsp]]
+            type = "high_pass"
+
+            [[dsp]]
+            type = "notch"
+            frequency_hz = 60.0
+            harmonics = 3
+
+            [[dsp]]
+            type = "noise_gate"
+        "#;
+        let cfg = parse_with(chain);
+        cfg.validate().unwrap();
+        assert_eq!(cfg.processing.dsp, vec![
+            DspStageConfig::HighPass { cutoff_hz: 80.0 },
+            DspStageConfig::Notch { frequency_hz: 60.0, q: 30.0, harmonics: 3 },
+            DspStageConfig::NoiseGate { threshold_db: -50.0, hold_ms: 100.0, release_ms: 50.0 },
+        ]);
+
+        assert!(parse_toml(&toml_with("[[dsp]]\ntype = \"reverb\"")).is_err());
+
+        let bad = parse_with("[[dsp]]\ntype = \"pre_emphasis\"\ncoefficient = 1.5");
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn audio_gain_goes_as_high_as_a_recommendation() {
+        let mut cfg = parse_with("");
+        cfg.audio_gain = MAX_GAIN;
+        cfg.validate().unwrap();
+        cfg.audio_gain = MAX_GAIN + 0.1;
+        assert!(cfg.validate().unwrap_err().to_string().contains("audio_gain"));
+    }
+
+    #[test]
+    fn set_audio_gain_keeps_comments_and_layout() {
+        let content = "# Gain\r\n# audio_gain = 2\r\naudio_gain = 8   # was 4\r\nsample_rate = 16000\r\n\n[[inputs]]\naudio_gain = 3\n";
+        let updated = set_audio_gain(content, 5.34).unwrap();
+        assert_eq!(
+            updated,
+            "# Gain\r\n# audio_gain = 2\r\naudio_gain = 5.3   # was 4\r\nsample_rate = 16000\r\n\n[[inputs]]\naudio_gain = 3\n"
+        );
+
+        assert_eq!(set_audio_gain("  audio_gain=1.0", 2.0).unwrap(), "  audio_gain=2.0");
+        assert!(set_audio_gain("# audio_gain = 1.0\n", 2.0).is_err());
+    }
 }
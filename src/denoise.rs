@@ -0,0 +1,356 @@
+//! Spectral noise suppression on the resampled stream.
+//!
+//! Steady background noise (fans, hum, hiss) is removed in the frequency
+//! domain: the signal is cut into overlapping frames, each frame's spectrum
+//! is compared with a learned noise spectrum, and bins that are mostly noise
+//! are turned down.  The gain per bin is a power spectral subtraction with a
+//! floor of `noise_reduction_db`, smoothed over time so the residual noise
+//! doesn't turn into "musical" chirps.
+//!
+//! The noise profile comes either from the first `noise_learn_ms` of the
+//! recording or, continuously, from frames a speech detector marks as silent.
+//! Until a profile exists audio passes through unchanged; analysis and
+//! synthesis windows reconstruct the input exactly in that case.
+
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::vad::{EnergyDetector, SpeechDetector};
+
+/// Analysis frame length; rounded up to a power of two samples.
+const FRAME_MS: u32 = 32;
+/// Noise is subtracted this many times over, which trades a little speech
+/// for much less residual noise.
+const OVERSUBTRACTION: f32 = 1.5;
+/// Share of the previous frame's gain kept per bin.
+const GAIN_SMOOTHING: f32 = 0.6;
+/// How quickly the profile follows silent frames in `noise_learn = "vad"` mode.
+const NOISE_ADAPT: f32 = 0.05;
+
+/// Which output streams get the suppressed audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseTarget {
+    Wav,
+    Recognizer,
+    Both,
+}
+
+impl NoiseTarget {
+    /// `None` when `noise_suppression = "off"`.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        match config.noise_suppression.as_str() {
+            "wav" => Some(Self::Wav),
+            "recognizer" => Some(Self::Recognizer),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+}
+
+/// Where the noise profile comes from.
+pub enum NoiseLearning {
+    /// Average the frames in the first this many samples.
+    LeadIn(usize),
+    /// Keep following frames the detector classifies as non-speech.
+    Silence(Box<dyn SpeechDetector>),
+}
+
+pub struct NoiseSuppressor {
+    frame_len: usize,
+    hop: usize,
+    /// Square-root Hann, used for both analysis and synthesis.
+    window: Vec<f32>,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    time: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    /// Input not yet consumed by a whole frame.  Starts with `frame_len - hop`
+    /// zeros so the first real samples get full overlap.
+    pending: Vec<f32>,
+    /// Overlap-add accumulator, one frame long.
+    overlap: Vec<f32>,
+    /// Output still to be dropped because it belongs to the zero lead-in.
+    skip: usize,
+    learning: NoiseLearning,
+    /// Per-bin noise power and how many frames went into it.
+    noise: Vec<f32>,
+    noise_frames: usize,
+    gains: Vec<f32>,
+    floor: f32,
+    frames: u64,
+    consumed: u64,
+    produced: u64,
+}
+
+impl NoiseSuppressor {
+    /// `reduction_db` is the most a bin is ever turned down.
+    pub fn new(sample_rate: u32, learning: NoiseLearning, reduction_db: f32) -> Self {
+        let frame_len = ((sample_rate * FRAME_MS / 1000) as usize).next_power_of_two().max(16);
+        let hop = frame_len / 2;
+        let window = (0..frame_len)
+            .map(|i| (0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / frame_len as f32).cos()).sqrt())
+            .collect();
+
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(frame_len);
+        let inverse = planner.plan_fft_inverse(frame_len);
+        let time = forward.make_input_vec();
+        let spectrum = forward.make_output_vec();
+        let bins = spectrum.len();
+
+        Self {
+            frame_len,
+            hop,
+            window,
+            forward,
+            inverse,
+            time,
+            spectrum,
+            pending: vec![0.0; frame_len - hop],
+            overlap: vec![0.0; frame_len],
+            skip: frame_len - hop,
+            learning,
+            noise: vec![0.0; bins],
+            noise_frames: 0,
+            gains: vec![1.0; bins],
+            floor: 10f32.powf(-reduction_db / 20.0),
+            frames: 0,
+            consumed: 0,
+            produced: 0,
+        }
+    }
+
+    /// Suppressor for the output stream, or `None` when it's switched off.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        NoiseTarget::from_config(config)?;
+        let learning = match config.noise_learn.as_str() {
+            "vad" => NoiseLearning::Silence(Box::new(EnergyDetector::new(config.vad_threshold_db))),
+            _ => NoiseLearning::LeadIn((config.sample_rate as u64 * config.noise_learn_ms as u64 / 1000) as usize),
+        };
+        Some(Self::new(config.sample_rate, learning, config.noise_reduction_db))
+    }
+
+    /// Suppress noise in `samples`, appending the result to `out`.  Output
+    /// lags input by half a frame, so the first call returns less than it
+    /// was given; `flush` returns the rest.
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        self.consumed += samples.len() as u64;
+        self.pending.extend_from_slice(samples);
+        let start = out.len();
+        self.run_frames(out);
+        self.produced += (out.len() - start) as u64;
+    }
+
+    /// Append the samples still held in the overlap to `out`; ends the stream.
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        let owed = (self.consumed - self.produced) as usize;
+        let start = out.len();
+        self.pending.resize(self.pending.len() + self.frame_len, 0.0);
+        self.run_frames(out);
+        out.truncate(start + owed);
+        self.produced += (out.len() - start) as u64;
+    }
+
+    /// Frames that went into the noise profile; 0 means nothing was suppressed.
+    pub fn noise_frames(&self) -> usize {
+        self.noise_frames
+    }
+
+    fn run_frames(&mut self, out: &mut Vec<f32>) {
+        while self.pending.len() >= self.frame_len {
+            self.process_frame();
+
+            if self.skip > 0 {
+                self.skip -= self.hop;
+            } else {
+                out.extend_from_slice(&self.overlap[..self.hop]);
+            }
+            self.overlap.copy_within(self.hop.., 0);
+            let tail = self.frame_len - self.hop;
+            self.overlap[tail..].fill(0.0);
+            self.pending.drain(..self.hop);
+        }
+    }
+
+    fn process_frame(&mut self) {
+        let frame = &self.pending[..self.frame_len];
+        for ((t, &s), &w) in self.time.iter_mut().zip(frame).zip(&self.window) {
+            *t = s * w;
+        }
+        self.forward
+            .process(&mut self.time, &mut self.spectrum)
+            .expect("FFT buffers are sized by the plan");
+
+        // The first frame is half zero lead-in, so it says nothing about the noise
+        let index = self.frames;
+        self.frames += 1;
+        if index > 0 {
+            self.learn(index);
+        }
+
+        if self.noise_frames > 0 && self.profile_ready(index) {
+            for ((bin, gain), &noise) in self.spectrum.iter_mut().zip(&mut self.gains).zip(&self.noise) {
+                let power = bin.norm_sqr();
+                let target = if power > 0.0 {
+                    (1.0 - OVERSUBTRACTION * noise / power).max(0.0).sqrt()
+                } else {
+                    0.0
+                };
+                *gain = (GAIN_SMOOTHING * *gain + (1.0 - GAIN_SMOOTHING) * target).max(self.floor);
+                *bin *= *gain;
+            }
+        }
+
+        // DC and Nyquist must stay real for the inverse transform
+        let last = self.spectrum.len() - 1;
+        self.spectrum[0].im = 0.0;
+        self.spectrum[last].im = 0.0;
+        self.inverse
+            .process(&mut self.spectrum, &mut self.time)
+            .expect("FFT buffers are sized by the plan");
+
+        let scale = 1.0 / self.frame_len as f32;
+        for ((o, &t), &w) in self.overlap.iter_mut().zip(&self.time).zip(&self.window) {
+            *o += t * w * scale;
+        }
+    }
+
+    /// Frame `index` starts at sample `(index - 1) * hop` of the real input.
+    fn frame_start(&self, index: u64) -> usize {
+        (index as usize).saturating_sub(1) * self.hop
+    }
+
+    fn learn(&mut self, index: u64) {
+        let start = self.frame_start(index);
+        match &mut self.learning {
+            NoiseLearning::LeadIn(samples) => {
+                if start + self.frame_len > *samples && self.noise_frames > 0 {
+                    return;
+                }
+                let n = self.noise_frames as f32;
+                for (noise, bin) in self.noise.iter_mut().zip(&self.spectrum) {
+                    *noise = (*noise * n + bin.norm_sqr()) / (n + 1.0);
+                }
+                self.noise_frames += 1;
+            }
+            NoiseLearning::Silence(detector) => {
+                if detector.is_speech(&self.pending[..self.frame_len]) {
+                    return;
+                }
+                let rate = if self.noise_frames == 0 { 1.0 } else { NOISE_ADAPT };
+                for (noise, bin) in self.noise.iter_mut().zip(&self.spectrum) {
+                    *noise += rate * (bin.norm_sqr() - *noise);
+                }
+                self.noise_frames += 1;
+            }
+        }
+    }
+
+    /// Lead-in profiles are only used once the lead-in is over.
+    fn profile_ready(&self, index: u64) -> bool {
+        match self.learning {
+            NoiseLearning::LeadIn(samples) => self.frame_start(index) >= samples,
+            NoiseLearning::Silence(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn noise(seconds: f32, amplitude: f32) -> Vec<f32> {
+        let mut state = 0x9e37_79b9u32;
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn tone(seconds: f32, amplitude: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn run(suppressor: &mut NoiseSuppressor, input: &[f32]) -> Vec<f32> {
+        let mut out = Vec::new();
+        for chunk in input.chunks(333) {
+            suppressor.process(chunk, &mut out);
+        }
+        suppressor.flush(&mut out);
+        out
+    }
+
+    fn power(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32
+    }
+
+    fn lead_in(ms: usize) -> NoiseLearning {
+        NoiseLearning::LeadIn(RATE as usize * ms / 1000)
+    }
+
+    #[test]
+    fn passes_audio_through_exactly_while_learning() {
+        let mut suppressor = NoiseSuppressor::new(RATE, lead_in(500), 12.0);
+        let input = noise(0.3, 0.2);
+        let out = run(&mut suppressor, &input);
+        assert_eq!(out.len(), input.len());
+        for (a, b) in out.iter().zip(&input) {
+            assert!((a - b).abs() < 1e-4, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn tone_in_white_noise_gets_cleaner() {
+        let mut suppressor = NoiseSuppressor::new(RATE, lead_in(500), 12.0);
+        let hiss = noise(3.0, 0.02);
+        let clean = [vec![0.0; RATE as usize], tone(2.0, 0.2)].concat();
+        let input: Vec<f32> = clean.iter().zip(&hiss).map(|(c, n)| c + n).collect();
+        let out = run(&mut suppressor, &input);
+        assert_eq!(out.len(), input.len());
+        assert!(suppressor.noise_frames() > 0);
+
+        // Noise alone (after the lead-in) is turned down
+        let quiet = RATE as usize / 2..RATE as usize;
+        let reduction_db = 10.0 * (power(&input[quiet.clone()]) / power(&out[quiet])).log10();
+        assert!(reduction_db > 6.0, "noise down {:.1} dB", reduction_db);
+
+        // The tone survives with far less noise around it
+        let body = 2 * RATE as usize..3 * RATE as usize;
+        let residual = |signal: &[f32]| -> Vec<f32> {
+            signal[body.clone()].iter().zip(&clean[body.clone()]).map(|(s, c)| s - c).collect()
+        };
+        let snr_in = power(&clean[body.clone()]) / power(&residual(&input));
+        let snr_out = power(&clean[body.clone()]) / power(&residual(&out));
+        let gain_db = 10.0 * (snr_out / snr_in).log10();
+        assert!(gain_db > 6.0, "SNR improved by {:.1} dB", gain_db);
+    }
+
+    #[test]
+    fn silence_mode_learns_from_pauses_not_the_lead_in() {
+        // Loud tone first, so a lead-in profile would contain no hiss at all
+        let input = [tone(0.5, 0.3), noise(2.0, 0.02)].concat();
+        let tail = input.len() - RATE as usize / 2..input.len();
+
+        let mut lead = NoiseSuppressor::new(RATE, lead_in(500), 12.0);
+        let out = run(&mut lead, &input);
+        let lead_db = 10.0 * (power(&input[tail.clone()]) / power(&out[tail.clone()])).log10();
+
+        let detector = Box::new(EnergyDetector::new(12.0));
+        let mut silence = NoiseSuppressor::new(RATE, NoiseLearning::Silence(detector), 12.0);
+        let out = run(&mut silence, &input);
+        let silence_db = 10.0 * (power(&input[tail.clone()]) / power(&out[tail])).log10();
+
+        assert!(lead_db < 2.0, "lead-in profile removed {:.1} dB", lead_db);
+        assert!(silence_db > 6.0, "silence profile removed {:.1} dB", silence_db);
+    }
+}
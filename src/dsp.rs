@@ -0,0 +1,392 @@
+//! The `[[dsp]]` processing chain run between downmix and resampling.
+//!
+//! Each `[[dsp]]` table in config.toml becomes one stage, applied in file
+//! order at the device sample rate.  Most stages are simple IIR filters
+//! working sample by sample; the limiter delays the signal by its
+//! look-ahead, so the chain has to be flushed at the end of a recording.
+
+use anyhow::Result;
+use std::f64::consts::PI;
+
+use crate::config::DspStageConfig;
+use crate::limiter::SoftLimiter;
+
+/// One processing step.  Stages may hold samples back (look-ahead) and
+/// return them from `flush`.
+pub trait DspStage: Send {
+    fn process(&mut self, samples: Vec<f32>) -> Vec<f32>;
+
+    fn flush(&mut self) -> Vec<f32> {
+        Vec::new()
+    }
+}
+
+pub struct DspChain {
+    stages: Vec<Box<dyn DspStage>>,
+}
+
+impl DspChain {
+    pub fn new(stages: &[DspStageConfig], sample_rate: u32) -> Result<Self> {
+        let nyquist = sample_rate as f32 / 2.0;
+        let stages = stages.iter().map(|stage| -> Result<Box<dyn DspStage>> {
+            Ok(match *stage {
+                DspStageConfig::DcBlocker => Box::new(DcBlocker::new(sample_rate)),
+                DspStageConfig::HighPass { cutoff_hz } => {
+                    if cutoff_hz >= nyquist {
+                        anyhow::bail!("high_pass cutoff_hz {} must be below {} Hz at this sample rate", cutoff_hz, nyquist);
+                    }
+                    Box::new(Biquad::high_pass(sample_rate, cutoff_hz))
+                }
+                DspStageConfig::Notch { frequency_hz, q, harmonics } => {
+                    let filters: Vec<Biquad> = (1..=harmonics)
+                        .map(|h| frequency_hz * h as f32)
+                        .take_while(|&f| f < nyquist)
+                        .map(|f| Biquad::notch(sample_rate, f, q))
+                        .collect();
+                    if filters.is_empty() {
+                        anyhow::bail!("notch frequency_hz {} must be below {} Hz at this sample rate", frequency_hz, nyquist);
+                    }
+                    Box::new(Cascade(filters))
+                }
+                DspStageConfig::PreEmphasis { coefficient } => Box::new(PreEmphasis { coefficient, previous: 0.0 }),
+                DspStageConfig::Gain { db } => Box::new(Gain(10f32.powf(db / 20.0))),
+                DspStageConfig::Limiter { threshold_db, release_ms } => {
+                    Box::new(SoftLimiter::new(sample_rate, threshold_db, release_ms))
+                }
+                DspStageConfig::NoiseGate { threshold_db, hold_ms, release_ms } => {
+                    Box::new(NoiseGate::new(sample_rate, threshold_db, hold_ms, release_ms))
+                }
+            })
+        }).collect::<Result<Vec<_>>>()?;
+        Ok(Self { stages })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn process(&mut self, samples: Vec<f32>) -> Vec<f32> {
+        self.stages.iter_mut().fold(samples, |samples, stage| stage.process(samples))
+    }
+
+    /// Drain held-back samples through the rest of the chain.
+    pub fn flush(&mut self) -> Vec<f32> {
+        let mut tail = Vec::new();
+        for stage in &mut self.stages {
+            tail = stage.process(tail);
+            tail.extend(stage.flush());
+        }
+        tail
+    }
+}
+
+impl DspStage for SoftLimiter {
+    fn process(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+        SoftLimiter::process(self, &mut samples);
+        samples
+    }
+
+    fn flush(&mut self) -> Vec<f32> {
+        let mut out = Vec::new();
+        SoftLimiter::flush(self, &mut out);
+        out
+    }
+}
+
+// ── Filters ──────────────────────────────────────────────────────────────────
+
+/// First-order DC blocker: `y[n] = x[n] - x[n-1] + r * y[n-1]`, corner ≈ 20 Hz.
+struct DcBlocker {
+    r: f32,
+    previous_in: f32,
+    previous_out: f32,
+}
+
+impl DcBlocker {
+    fn new(sample_rate: u32) -> Self {
+        let r = (-2.0 * PI * 20.0 / sample_rate as f64).exp() as f32;
+        Self { r, previous_in: 0.0, previous_out: 0.0 }
+    }
+}
+
+impl DspStage for DcBlocker {
+    fn process(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+        for s in samples.iter_mut() {
+            let y = *s - self.previous_in + self.r * self.previous_out;
+            self.previous_in = *s;
+            self.previous_out = y;
+            *s = y;
+        }
+        samples
+    }
+}
+
+/// Second-order IIR section (RBJ cookbook coefficients, transposed direct form II).
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn from_coefficients(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+            state: [0.0; 2],
+        }
+    }
+
+    /// Butterworth high-pass.
+    fn high_pass(sample_rate: u32, cutoff_hz: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz as f64 / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * std::f64::consts::FRAC_1_SQRT_2);
+        let cos = w0.cos();
+        Self::from_coefficients(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Band-reject at `frequency_hz`; higher `q` means a narrower notch.
+    fn notch(sample_rate: u32, frequency_hz: f32, q: f32) -> Self {
+        let w0 = 2.0 * PI * frequency_hz as f64 / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * q as f64);
+        let cos = w0.cos();
+        Self::from_coefficients([1.0, -2.0 * cos, 1.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    fn tick(&mut self, x: f32) -> f32 {
+        let x = x as f64;
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y as f32
+    }
+}
+
+impl DspStage for Biquad {
+    fn process(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+        for s in samples.iter_mut() {
+            *s = self.tick(*s);
+        }
+        samples
+    }
+}
+
+/// Several biquads in series (a notch per hum harmonic).
+struct Cascade(Vec<Biquad>);
+
+impl DspStage for Cascade {
+    fn process(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+        for s in samples.iter_mut() {
+            *s = self.0.iter_mut().fold(*s, |x, filter| filter.tick(x));
+        }
+        samples
+    }
+}
+
+/// `y[n] = x[n] - coefficient * x[n-1]`: tilts the spectrum towards highs.
+struct PreEmphasis {
+    coefficient: f32,
+    previous: f32,
+}
+
+impl DspStage for PreEmphasis {
+    fn process(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+        for s in samples.iter_mut() {
+            let x = *s;
+            *s = x - self.coefficient * self.previous;
+            self.previous = x;
+        }
+        samples
+    }
+}
+
+struct Gain(f32);
+
+impl DspStage for Gain {
+    fn process(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+        for s in samples.iter_mut() {
+            *s *= self.0;
+        }
+        samples
+    }
+}
+
+// ── Noise gate ───────────────────────────────────────────────────────────────
+
+/// How quickly the gate opens once the level crosses the threshold.
+const GATE_ATTACK_MS: f32 = 1.0;
+/// Decay of the peak envelope the gate listens to.
+const GATE_ENVELOPE_MS: f32 = 10.0;
+
+/// Mutes the signal while its envelope stays below `threshold_db`.
+struct NoiseGate {
+    threshold: f32,
+    hold: usize,
+    envelope: f32,
+    envelope_decay: f32,
+    open_coef: f32,
+    close_coef: f32,
+    /// Samples left before a quiet gate starts closing.
+    hold_left: usize,
+    gain: f32,
+}
+
+impl NoiseGate {
+    fn new(sample_rate: u32, threshold_db: f32, hold_ms: f32, release_ms: f32) -> Self {
+        let coef = |ms: f32| 1.0 - (-1.0 / (sample_rate as f32 * ms / 1000.0).max(1.0)).exp();
+        Self {
+            threshold: 10f32.powf(threshold_db / 20.0),
+            hold: (sample_rate as f32 * hold_ms / 1000.0) as usize,
+            envelope: 0.0,
+            envelope_decay: 1.0 - coef(GATE_ENVELOPE_MS),
+            open_coef: coef(GATE_ATTACK_MS),
+            close_coef: coef(release_ms),
+            hold_left: 0,
+            gain: 0.0,
+        }
+    }
+}
+
+impl DspStage for NoiseGate {
+    fn process(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+        for s in samples.iter_mut() {
+            self.envelope = s.abs().max(self.envelope * self.envelope_decay);
+            let open = if self.envelope >= self.threshold {
+                self.hold_left = self.hold;
+                true
+            } else if self.hold_left > 0 {
+                self.hold_left -= 1;
+                true
+            } else {
+                false
+            };
+            if open {
+                self.gain += self.open_coef * (1.0 - self.gain);
+            } else {
+                self.gain -= self.close_coef * self.gain;
+            }
+            *s *= self.gain;
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    fn sine(freq: f32, seconds: f32, amplitude: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * freq * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    /// RMS of the second half, after filters have settled.
+    fn settled_rms(samples: &[f32]) -> f32 {
+        rms(&samples[samples.len() / 2..])
+    }
+
+    fn chain(stages: Vec<DspStageConfig>) -> DspChain {
+        DspChain::new(&stages, RATE).unwrap()
+    }
+
+    #[test]
+    fn dc_blocker_removes_offset() {
+        let mut dc = chain(vec![DspStageConfig::DcBlocker]);
+        let input: Vec<f32> = sine(440.0, 1.0, 0.3).iter().map(|s| s + 0.5).collect();
+        let out = dc.process(input);
+        let tail = &out[out.len() / 2..];
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+        assert!(mean.abs() < 0.01, "mean {}", mean);
+        assert!((settled_rms(&out) - 0.3 / 2f32.sqrt()).abs() < 0.01);
+    }
+
+    #[test]
+    fn high_pass_cuts_rumble_and_keeps_voice() {
+        let stage = || chain(vec![DspStageConfig::HighPass { cutoff_hz: 100.0 }]);
+        let rumble = stage().process(sine(20.0, 2.0, 0.5));
+        let voice = stage().process(sine(1000.0, 1.0, 0.5));
+        assert!(settled_rms(&rumble) < 0.05 * rms(&sine(20.0, 2.0, 0.5)), "rumble {}", settled_rms(&rumble));
+        assert!((settled_rms(&voice) / rms(&sine(1000.0, 1.0, 0.5)) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn notch_removes_mains_hum_and_harmonics() {
+        let notch = || chain(vec![DspStageConfig::Notch { frequency_hz: 50.0, q: 10.0, harmonics: 3 }]);
+        for hum in [50.0, 100.0, 150.0] {
+            let out = notch().process(sine(hum, 3.0, 0.5));
+            assert!(settled_rms(&out) < 0.01 * 0.5, "{} Hz left {}", hum, settled_rms(&out));
+        }
+        let voice = notch().process(sine(440.0, 1.0, 0.5));
+        assert!((settled_rms(&voice) / rms(&sine(440.0, 1.0, 0.5)) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn notch_above_nyquist_is_rejected() {
+        let stage = DspStageConfig::Notch { frequency_hz: 30000.0, q: 10.0, harmonics: 1 };
+        assert!(DspChain::new(&[stage], RATE).is_err());
+        assert!(DspChain::new(&[DspStageConfig::HighPass { cutoff_hz: 24000.0 }], RATE).is_err());
+    }
+
+    #[test]
+    fn pre_emphasis_is_first_difference() {
+        let mut pre = chain(vec![DspStageConfig::PreEmphasis { coefficient: 0.97 }]);
+        let out = pre.process(vec![1.0, 0.0, 0.0]);
+        assert_eq!(out, vec![1.0, -0.97, 0.0]);
+        // Boosts highs relative to lows
+        let low = settled_rms(&chain(vec![DspStageConfig::PreEmphasis { coefficient: 0.97 }]).process(sine(100.0, 1.0, 0.5)));
+        let high = settled_rms(&chain(vec![DspStageConfig::PreEmphasis { coefficient: 0.97 }]).process(sine(4000.0, 1.0, 0.5)));
+        assert!(high > 5.0 * low);
+    }
+
+    #[test]
+    fn gain_scales_by_decibels() {
+        let mut gain = chain(vec![DspStageConfig::Gain { db: 6.0 }]);
+        let out = gain.process(vec![0.25, -0.1]);
+        assert!((out[0] - 0.25 * 1.9953).abs() < 1e-3);
+        assert!((out[1] + 0.1 * 1.9953).abs() < 1e-3);
+    }
+
+    #[test]
+    fn limiter_stage_caps_peaks_and_flushes_tail() {
+        let mut limiter = chain(vec![
+            DspStageConfig::Gain { db: 12.0 },
+            DspStageConfig::Limiter { threshold_db: -3.0, release_ms: 50.0 },
+        ]);
+        let input = sine(200.0, 1.0, 0.5);
+        let mut out = Vec::new();
+        for chunk in input.chunks(1000) {
+            out.extend(limiter.process(chunk.to_vec()));
+        }
+        out.extend(limiter.flush());
+        assert_eq!(out.len(), input.len());
+        let peak = out.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak <= 10f32.powf(-3.0 / 20.0) + 1e-4, "peak {}", peak);
+    }
+
+    #[test]
+    fn noise_gate_mutes_quiet_noise_and_passes_speech() {
+        let gate = || chain(vec![DspStageConfig::NoiseGate { threshold_db: -40.0, hold_ms: 50.0, release_ms: 20.0 }]);
+        // -52 dBFS hiss is muted
+        let hiss = gate().process(sine(3000.0, 1.0, 0.0025));
+        assert!(settled_rms(&hiss) < 1e-4, "hiss {}", settled_rms(&hiss));
+        // A loud tone passes at full level
+        let tone = gate().process(sine(300.0, 1.0, 0.3));
+        assert!((settled_rms(&tone) / rms(&sine(300.0, 1.0, 0.3)) - 1.0).abs() < 0.01);
+
+        // And the gate closes again after the tone stops
+        let mut g = gate();
+        g.process(sine(300.0, 0.5, 0.3));
+        let after = g.process(sine(3000.0, 0.5, 0.0025));
+        assert!(rms(&after[after.len() / 2..]) < 1e-4);
+    }
+}
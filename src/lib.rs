@@ -0,0 +1,26 @@
+//! Private Speech-to-Text: the recording pipeline, recognizers and
+//! reports behind the `pstt` binary.
+
+pub mod config;
+pub mod audio;
+pub mod analysis;
+pub mod capture;
+pub mod calibrate;
+pub mod source;
+pub mod input;
+pub mod buffers;
+pub mod bus;
+pub mod resampler;
+pub mod limiter;
+pub mod meter;
+pub mod status_line;
+pub mod dsp;
+pub mod denoise;
+pub mod vad;
+pub mod writer;
+pub mod recognition;
+pub mod text_writer;
+pub mod whisper;
+pub mod summary;
+#[cfg(feature = "sherpa-engine")]
+pub mod sherpa;
@@ -0,0 +1,222 @@
+//! Look-ahead soft limiter that replaces hard clamping after gain.
+//!
+//! Clamping flattens every sample above 1.0, which is exactly the distortion
+//! `docs/CLIPPING_EXPLAINED.md` warns about.  The limiter instead delays the
+//! signal by a few milliseconds, sees peaks coming, and lowers the gain
+//! smoothly ahead of them so they land at `threshold` with their shape
+//! intact.  The gain then recovers with the configured release time.
+//!
+//! Per output sample the applied gain is the minimum required gain over the
+//! look-ahead window (with a one-pole release), averaged over the window
+//! length.  Every value in that average already satisfies the peak at the
+//! current sample, so the output never exceeds the threshold.
+
+use std::collections::VecDeque;
+
+/// How far ahead the limiter looks (and how much it delays the signal).
+const LOOKAHEAD_MS: f32 = 5.0;
+
+pub struct SoftLimiter {
+    threshold: f32,
+    /// Per-sample recovery towards unity gain.
+    release_coef: f32,
+    lookahead: usize,
+    /// Input delayed by `lookahead` samples.  Starts out holding virtual
+    /// silence so the very first peak is covered by a full window.
+    delay: VecDeque<f32>,
+    /// Virtual samples still at the front of `delay`; their output is dropped.
+    virtual_held: usize,
+    /// Sliding minimum of required gains: (input index, gain), increasing gains.
+    minima: VecDeque<(u64, f32)>,
+    /// Released gains over the last `lookahead` samples and their sum.
+    window: VecDeque<f32>,
+    window_sum: f64,
+    released: f32,
+    input_index: u64,
+    limited: u64,
+    processed: u64,
+    input_peak: f32,
+}
+
+impl SoftLimiter {
+    /// `threshold_db` is the output ceiling in dBFS; `release_ms` how long
+    /// the gain takes to (mostly) recover after a peak.
+    pub fn new(sample_rate: u32, threshold_db: f32, release_ms: f32) -> Self {
+        let lookahead = ((sample_rate as f32 * LOOKAHEAD_MS / 1000.0) as usize).max(1);
+        let release_samples = (sample_rate as f32 * release_ms / 1000.0).max(1.0);
+        let mut window = VecDeque::with_capacity(lookahead);
+        window.extend(std::iter::repeat_n(1.0, lookahead));
+        let mut delay = VecDeque::with_capacity(lookahead + 1);
+        delay.extend(std::iter::repeat_n(0.0, lookahead));
+        Self {
+            threshold: 10f32.powf(threshold_db / 20.0),
+            release_coef: 1.0 - (-1.0 / release_samples).exp(),
+            lookahead,
+            delay,
+            virtual_held: lookahead,
+            minima: VecDeque::new(),
+            window,
+            window_sum: lookahead as f64,
+            released: 1.0,
+            // The virtual lead-in occupies indices 0..lookahead
+            input_index: lookahead as u64,
+            limited: 0,
+            processed: 0,
+            input_peak: 0.0,
+        }
+    }
+
+    /// Limit `samples` in place.  Output lags input by the look-ahead, so the
+    /// first calls shorten `samples`; `flush` returns the rest.
+    pub fn process(&mut self, samples: &mut Vec<f32>) {
+        let mut written = 0;
+        for read in 0..samples.len() {
+            let s = samples[read];
+            self.processed += 1;
+            self.input_peak = self.input_peak.max(s.abs());
+            if s.abs() > self.threshold {
+                self.limited += 1;
+            }
+            // Never ahead of `read`, so nothing unread is overwritten
+            if let Some(y) = self.push(s) {
+                samples[written] = y;
+                written += 1;
+            }
+        }
+        samples.truncate(written);
+    }
+
+    /// Append the samples still held in the look-ahead delay to `out`.
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        let mut held = self.delay.len() - self.virtual_held;
+        while held > 0 {
+            if let Some(y) = self.push(0.0) {
+                out.push(y);
+                held -= 1;
+            }
+        }
+        // The trailing silence just pushed becomes the next stream's lead-in
+        self.virtual_held = self.delay.len();
+    }
+
+    /// Input samples that exceeded the threshold, out of all processed.
+    pub fn limited(&self) -> (u64, u64) {
+        (self.limited, self.processed)
+    }
+
+    /// Highest absolute input level seen.
+    pub fn input_peak(&self) -> f32 {
+        self.input_peak
+    }
+
+    fn push(&mut self, s: f32) -> Option<f32> {
+        let required = if s.abs() > self.threshold { self.threshold / s.abs() } else { 1.0 };
+        let index = self.input_index;
+        self.input_index += 1;
+
+        while self.minima.back().is_some_and(|&(_, g)| g >= required) {
+            self.minima.pop_back();
+        }
+        self.minima.push_back((index, required));
+        self.delay.push_back(s);
+
+        // The sample leaving the delay is `lookahead` behind; the window
+        // minimum covers it and everything up to the newest input
+        let out_index = index - self.lookahead as u64;
+        while self.minima.front().is_some_and(|&(i, _)| i < out_index) {
+            self.minima.pop_front();
+        }
+        let window_min = self.minima.front().map_or(1.0, |&(_, g)| g);
+        self.released = (self.released + self.release_coef * (1.0 - self.released)).min(window_min);
+
+        self.window_sum += self.released as f64 - self.window.pop_front().unwrap_or(1.0) as f64;
+        self.window.push_back(self.released);
+        let gain = (self.window_sum / self.lookahead as f64) as f32;
+
+        let y = self.delay.pop_front().map(|x| x * gain);
+        if self.virtual_held > 0 {
+            self.virtual_held -= 1;
+            return None;
+        }
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn sine(amplitude: f32, seconds: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 200.0 * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn run(limiter: &mut SoftLimiter, input: &[f32]) -> Vec<f32> {
+        let mut out = Vec::new();
+        for chunk in input.chunks(500) {
+            let mut chunk = chunk.to_vec();
+            limiter.process(&mut chunk);
+            out.extend(chunk);
+        }
+        limiter.flush(&mut out);
+        out
+    }
+
+    #[test]
+    fn quiet_signal_passes_unchanged() {
+        let mut limiter = SoftLimiter::new(RATE, -1.0, 100.0);
+        let input = sine(0.5, 0.5);
+        let out = run(&mut limiter, &input);
+        assert_eq!(out.len(), input.len());
+        for (a, b) in out.iter().zip(&input) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        assert_eq!(limiter.limited().0, 0);
+    }
+
+    #[test]
+    fn loud_signal_stays_under_threshold_and_is_counted() {
+        let mut limiter = SoftLimiter::new(RATE, -1.0, 100.0);
+        let input = sine(3.0, 1.0);
+        let out = run(&mut limiter, &input);
+        let ceiling = 10f32.powf(-1.0 / 20.0);
+
+        assert_eq!(out.len(), input.len());
+        let peak = out.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak <= ceiling + 1e-4, "peak {}", peak);
+        // Turned down, not squashed: much of the wave is still loud
+        assert!(out.iter().filter(|s| s.abs() > 0.5).count() > input.len() / 4);
+
+        let (limited, processed) = limiter.limited();
+        assert_eq!(processed, input.len() as u64);
+        assert!(limited > processed / 2, "{} of {}", limited, processed);
+        assert!((limiter.input_peak() - 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn lookahead_catches_a_single_sample_spike() {
+        let mut limiter = SoftLimiter::new(RATE, -6.0, 50.0);
+        let mut input = vec![0.1; 2000];
+        input[1000] = 4.0;
+        let out = run(&mut limiter, &input);
+        assert!(out[1000].abs() <= 10f32.powf(-6.0 / 20.0) + 1e-4, "spike {}", out[1000]);
+        // Gain dips ahead of the spike rather than after it
+        assert!(out[990] < 0.1);
+    }
+
+    #[test]
+    fn gain_recovers_after_release() {
+        let mut limiter = SoftLimiter::new(RATE, -1.0, 50.0);
+        let input = [sine(3.0, 0.2), sine(0.2, 1.0)].concat();
+        let out = run(&mut limiter, &input);
+        // Well past the release time the quiet part is untouched again
+        let tail = RATE as usize / 2;
+        let n = out.len();
+        for (a, b) in out[n - tail..].iter().zip(&input[n - tail..]) {
+            assert!((a - b).abs() < 1e-3);
+        }
+    }
+}
@@ -1,15 +1,7 @@
-mod config;
-mod audio;
-mod input;
-mod buffers;
-mod resampler;
-mod writer;
-mod recognition;
-mod text_writer;
-mod whisper;
-mod summary;
-#[cfg(feature = "sherpa-engine")]
-mod sherpa;
+use pstt::{
+    analysis, audio, buffers, bus, calibrate, capture, config, dsp, input, recognition, resampler,
+    source, status_line, summary, text_writer, whisper, writer,
+};
 
 use clap::{Parser, Subcommand};
 use anyhow::{Result, Context};
@@ -17,12 +9,12 @@ use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::path::PathBuf;
 use crossterm::terminal::{enable_raw_mode, disable_raw_mode};
-use cpal::traits::{DeviceTrait, StreamTrait};
 use std::sync::mpsc;
 use chrono::Local;
 
-use config::Config;
-use buffers::{AudioPipeline};
+use config::{Config, InputProcessing};
+use buffers::{AudioPipeline, DroppedSamples};
+use bus::Tap;
 use input::{InputCommand, check_input};
 
 #[derive(Parser)]
@@ -35,6 +27,44 @@ struct Cli {
     #[arg(long)]
     build: bool,
 
+    /// Input device to record from: menu number, exact name or unique part
+    /// of the name (overrides `input_device` and `[[inputs]]` in config.toml).
+    /// Repeat as `--device <label>=<device>` to record several at once
+    #[arg(long, global = true)]
+    device: Vec<String>,
+
+    /// Record from a WAV file instead of a microphone
+    #[arg(long, conflicts_with_all = ["input_stdin", "input_synth"])]
+    input_file: Option<PathBuf>,
+
+    /// Replay --input-file as fast as possible instead of in real time
+    #[arg(long, requires = "input_file")]
+    fast: bool,
+
+    /// Record raw interleaved PCM read from stdin until EOF
+    #[arg(long, conflicts_with = "input_synth")]
+    input_stdin: bool,
+
+    /// Sample rate of the --input-stdin data (Hz)
+    #[arg(long, default_value_t = 16000, requires = "input_stdin")]
+    stdin_rate: u32,
+
+    /// Channel count of the --input-stdin data
+    #[arg(long, default_value_t = 1, requires = "input_stdin")]
+    stdin_channels: u16,
+
+    /// Sample encoding of the --input-stdin data
+    #[arg(long, value_enum, default_value = "s16le", requires = "input_stdin")]
+    stdin_format: source::PcmEncoding,
+
+    /// Record a synthetic test signal: "tone:<hz>", "noise" or "silence"
+    #[arg(long)]
+    input_synth: Option<String>,
+
+    /// Length of the --input-synth signal in seconds
+    #[arg(long, default_value_t = 10.0, requires = "input_synth")]
+    duration: f32,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -51,117 +81,210 @@ enum Commands {
         /// Path to the transcript TXT file
         txt_file: String,
     },
+    /// List input devices with their supported stream configurations
+    Devices {
+        /// Print machine-readable JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Report levels, SNR and DC offset of a WAV file (no Whisper model needed)
+    Analyze {
+        /// Path to the WAV file (can be just filename if in output directory)
+        wav_file: String,
+        /// Also show stats per window of this length, e.g. "10s", "500ms", "2m"
+        #[arg(long, value_parser = parse_duration_secs)]
+        window: Option<f64>,
+        /// Print machine-readable JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Record a short passage and suggest (and optionally save) audio_gain
+    Calibrate {
+        /// How long to record, in seconds
+        #[arg(long, default_value_t = 20.0)]
+        seconds: f32,
+    },
+}
+
+/// One input to record.  The label tags transcript lines and file names
+/// when several inputs are recorded at once; a lone input needs none.
+struct SessionInput {
+    label: Option<String>,
+    source: Box<dyn source::AudioSource>,
+    processing: InputProcessing,
+}
+
+/// The capture and queues of one input; each gets its own WAV file.
+struct Track {
+    label: Option<String>,
+    capture: capture::CaptureSupervisor,
+    pipeline: AudioPipeline,
+    wav_path: PathBuf,
 }
 
 struct RecordingSession {
-    stream: cpal::Stream,
-    threads: Vec<std::thread::JoinHandle<()>>,
-    stop_signal: Arc<AtomicBool>,
+    tracks: Vec<Track>,
+    // Joined stage by stage in `stop`, upstream first
+    resampler_threads: Vec<std::thread::JoinHandle<()>>,
+    consumer_threads: Vec<std::thread::JoinHandle<()>>,
+    text_writer_thread: std::thread::JoinHandle<()>,
     text_tx: mpsc::Sender<recognition::RecognizedText>,
     wav_path_rx: mpsc::Receiver<PathBuf>,
-    wav_path: PathBuf,
     realtime_txt_path: PathBuf,
     base_name: String,
 }
 
-struct RecordingOutput {
+/// A recorded input and the WAV file its writer finished.
+struct TrackOutput {
+    label: Option<String>,
     wav_path: PathBuf,
+}
+
+struct RecordingOutput {
+    /// One per input whose WAV writer finished, in the order the inputs
+    /// were given.
+    tracks: Vec<TrackOutput>,
     realtime_txt_path: PathBuf,
     base_name: String,
 }
 
 impl RecordingSession {
 
-    fn start(device: cpal::Device, config: Arc<Config>) -> Result<Self> {
-        let (device_name, device_config) = audio::get_device_info(&device)?;
-        log::info!("Using device: {} ({:?})", device_name, device_config);
+    fn start(inputs: Vec<SessionInput>, config: Arc<Config>) -> Result<Self> {
+        if inputs.is_empty() {
+            anyhow::bail!("No input to record from");
+        }
         
-        // Create audio pipeline with 10 seconds of buffer
-        let pipeline = AudioPipeline::new(48000 * 10);
-        let stop_signal = Arc::new(AtomicBool::new(false));
+        // Fail fast on a channel selection or DSP chain an input can't satisfy
+        for input in &inputs {
+            let format = input.source.format();
+            resampler::ChannelMixer::new(format.channels as usize, &input.processing)
+                .and_then(|_| dsp::DspChain::new(&input.processing.dsp, format.sample_rate))
+                .with_context(|| format!("Input {}", input.source.name()))?;
+        }
         
-        // Create text channel
+        // Create text channel (shared by all inputs, lines carry their label)
         let (text_tx, text_rx) = mpsc::channel::<recognition::RecognizedText>();
         
         // Create wav path channel
         let (wav_path_tx, wav_path_rx) = mpsc::channel::<PathBuf>();
         
-        let mut threads = Vec::new();
+        // Build consistent output paths
+        let base_name = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+        let realtime_txt_path = PathBuf::from(&config.output_directory)
+            .join(format!("{}_real-time.txt", base_name));
         
-        // Thread 1: Microphone capture (handled by cpal stream)
-        let raw_queue = Arc::clone(&pipeline.raw_queue);
-        let stream = device.build_input_stream(
-            &device_config.into(),
-            move |data: &[f32], _: &_| {
-                if !raw_queue.push(data.to_vec()) {
-                    log::warn!("Mic: Failed to push to raw queue (overflow)");
+        // Thread 1 (per input): Audio capture (cpal stream or other source,
+        // supervised for device loss).  All inputs are opened before any
+        // processing starts so a failing one doesn't leave the others running.
+        let mut tracks: Vec<Track> = Vec::new();
+        let mut track_infos = Vec::new();
+        for input in inputs {
+            let input_format = input.source.format();
+            log::info!(
+                "Using input: {}{} ({} Hz, {} ch)",
+                recognition::source_prefix(input.label.as_deref()),
+                input.source.name(),
+                input_format.sample_rate,
+                input_format.channels
+            );
+            
+            // Create audio pipeline with `buffer_seconds` of buffer per queue
+            let pipeline = AudioPipeline::new(input_format, &config);
+            let started = capture::CaptureSupervisor::start(
+                input.source,
+                Arc::clone(&pipeline.raw_queue),
+                text_tx.clone(),
+                input.label.clone(),
+                capture::RecoverySettings::default(),
+            );
+            let capture = match started {
+                Ok(capture) => capture,
+                Err(e) => {
+                    for track in tracks {
+                        track.capture.stop();
+                    }
+                    return Err(e);
                 }
-            },
-            |err| log::error!("Stream error: {}", err),
-            None,
-        )?;
-        
-        stream.play()?;
+            };
+            
+            let track_name = match &input.label {
+                Some(label) => format!("{}_{}", base_name, label),
+                None => base_name.clone(),
+            };
+            let wav_path = writer::build_wav_path(&config.output_directory, &track_name);
+            let sidecar = |suffix: &str| {
+                PathBuf::from(&config.output_directory).join(format!("{}_{}.json", track_name, suffix))
+            };
+            tracks.push(Track { label: input.label.clone(), capture, pipeline, wav_path });
+            track_infos.push(resampler::TrackInfo {
+                format: input_format,
+                label: input.label,
+                processing: input.processing,
+                speech_segments_path: config.vad_enabled.then(|| sidecar("speech")),
+                audio_stats_path: Some(sidecar("audio")),
+            });
+        }
         log::info!("Audio stream started");
+        status_line::begin();
         
-        // Thread 2: Resampler
-        let resampler_handle = {
-            let raw_q = Arc::clone(&pipeline.raw_queue);
-            let resampled_q_writer = Arc::clone(&pipeline.resampled_queue_writer);
-            let resampled_q_realtime = Arc::clone(&pipeline.resampled_queue_realtime);
-            let cfg = Arc::clone(&config);
-            let stop = Arc::clone(&stop_signal);
-            std::thread::spawn(move || {
-                resampler::resampler_thread(raw_q, resampled_q_writer, resampled_q_realtime, cfg, stop);
-                log::info!("Resampler thread exiting");
-            })
-        };
-        threads.push(resampler_handle);
+        let mut resampler_threads = Vec::new();
+        let mut consumer_threads = Vec::new();
+        
+        for (track, track_info) in tracks.iter().zip(track_infos) {
+            let label = track_info.label.clone();
+            // Subscribe before the resampler starts publishing, so no consumer
+            // misses the start of the recording
+            let wav_q = track.pipeline.subscribe("WAV", Tap::Recording, &config.writer_overflow);
+            let realtime_q = track.pipeline.subscribe("real-time", Tap::Recognition, &config.realtime_overflow);
+            
+            // Thread 2: Resampler
+            let resampler_handle = {
+                let raw_q = Arc::clone(&track.pipeline.raw_queue);
+                let bus = Arc::clone(&track.pipeline.bus);
+                let cfg = Arc::clone(&config);
+                std::thread::spawn(move || {
+                    resampler::resampler_thread(raw_q, bus, track_info, cfg);
+                    log::info!("Resampler thread exiting");
+                })
+            };
+            resampler_threads.push(resampler_handle);
 
-        // Build consistent output paths
-        let base_name = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
-        let wav_path = writer::build_wav_path(&config.output_directory, &base_name);
-        let realtime_txt_path = PathBuf::from(&config.output_directory)
-            .join(format!("{}_real-time.txt", base_name));
+            // Thread 3: WAV Writer
+            let writer_handle = {
+                let path_tx = wav_path_tx.clone();
+                let output_path = track.wav_path.clone();
+                let sample_rate = config.sample_rate;
+                std::thread::spawn(move || {
+                    match writer::writer_thread(wav_q, output_path, sample_rate) {
+                        Ok(path) => {
+                            log::info!("\n💾 Recording saved: {}", path.display());
+                            let _ = path_tx.send(path);
+                        },
+                        Err(e) => log::error!("Writer thread error: {}", e),
+                    }
+                    log::info!("WAV writer thread exiting");
+                })
+            };
+            consumer_threads.push(writer_handle);
 
-        // Thread 3: WAV Writer
-        let writer_handle = {
-            let resampled_q = Arc::clone(&pipeline.resampled_queue_writer);
-            let stop = Arc::clone(&stop_signal);
-            let path_tx = wav_path_tx.clone();
-            let output_path = wav_path.clone();
-            let sample_rate = config.sample_rate;
-            std::thread::spawn(move || {
-                match writer::writer_thread(resampled_q, output_path, sample_rate, stop) {
-                    Ok(path) => {
-                        log::info!("\n💾 Recording saved: {}", path.display());
-                        let _ = path_tx.send(path);
-                    },
-                    Err(e) => log::error!("Writer thread error: {}", e),
-                }
-                log::info!("WAV writer thread exiting");
-            })
-        };
-        threads.push(writer_handle);
-
-        // Thread 4: Real-Time Recognition
-        let recognition_handle = {
-            let resampled_q = Arc::clone(&pipeline.resampled_queue_realtime);
-            let cfg = Arc::clone(&config);
-            let stop = Arc::clone(&stop_signal);
-            let tx = text_tx.clone();
-            std::thread::spawn(move || {
-                match recognition::realtime_recognition_thread(resampled_q, tx, cfg, stop) {
-                    Ok(_) => log::info!("Real-time recognition completed"),
-                    Err(e) => log::error!("Real-time recognition thread error: {}", e),
-                }
-                log::info!("Real-time recognition thread exiting");
-            })
-        };
-        threads.push(recognition_handle);
+            // Thread 4: Real-Time Recognition
+            let recognition_handle = {
+                let cfg = Arc::clone(&config);
+                let tx = text_tx.clone();
+                std::thread::spawn(move || {
+                    match recognition::realtime_recognition_thread(realtime_q, tx, label, cfg) {
+                        Ok(_) => log::info!("Real-time recognition completed"),
+                        Err(e) => log::error!("Real-time recognition thread error: {}", e),
+                    }
+                    log::info!("Real-time recognition thread exiting");
+                })
+            };
+            consumer_threads.push(recognition_handle);
+        }
 
         // Thread 5: Text Writer
-        let text_writer_handle = {
+        let text_writer_thread = {
             let output_path = realtime_txt_path.to_string_lossy().to_string();
             std::thread::spawn(move || {
                 match text_writer::text_writer_thread(text_rx, output_path) {
@@ -171,49 +294,120 @@ impl RecordingSession {
                 log::info!("Text writer thread exiting");
             })
         };
-        threads.push(text_writer_handle);
         
         Ok(Self {
-            stream,
-            threads,
-            stop_signal,
+            tracks,
+            resampler_threads,
+            consumer_threads,
+            text_writer_thread,
             text_tx,
             wav_path_rx,
-            wav_path,
             realtime_txt_path,
             base_name,
         })
     }
     
+    /// True once every finite input (file, stdin, synthetic) has been fully captured.
+    fn is_finished(&self) -> bool {
+        self.tracks.iter().all(|t| t.capture.is_finished())
+    }
+    
     fn stop(self) -> Option<RecordingOutput> {
         log::info!("Stopping recording...");
+        status_line::end();
         
-        // Signal all threads to stop
-        self.stop_signal.store(true, Ordering::Relaxed);
+        // Shut down stage by stage so nothing downstream exits before its
+        // input is complete.  1: stop capture; each supervisor closes its
+        // raw queue once the source can no longer push into it
+        let mut outputs = Vec::new();
+        let mut pipelines = Vec::new();
+        for track in self.tracks {
+            track.capture.stop();
+            outputs.push(TrackOutput { label: track.label.clone(), wav_path: track.wav_path });
+            pipelines.push((track.label, track.pipeline));
+        }
         
-        // Stop the audio stream
-        drop(self.stream);
+        // 2: the resamplers drain the raw queues, flush and close their outputs
+        for thread in self.resampler_threads {
+            let _ = thread.join();
+        }
         
-        // Drop the text sender to close the channel
+        // 3: the bus consumers drain until their queues are closed
+        for thread in self.consumer_threads {
+            let _ = thread.join();
+        }
+        
+        // 4: the text writer finishes once every sender is gone
         drop(self.text_tx);
+        let _ = self.text_writer_thread.join();
         
-        // Wait for all threads to finish
-        for thread in self.threads {
-            let _ = thread.join();
+        let summary: Vec<String> = pipelines.iter()
+            .flat_map(|(label, pipeline)| {
+                let dropped = pipeline.dropped();
+                drop_summary(label.as_deref(), &dropped).into_iter()
+                    .chain(stopped_summary(label.as_deref(), &dropped))
+            })
+            .collect();
+        if summary.is_empty() {
+            log::info!("No audio was dropped");
+        }
+        for line in summary {
+            log::warn!("{}", line);
+            println!("⚠️  {}", line);
         }
         
-        // Try to receive the wav path (should be available after writer thread finishes)
-        let wav_path = self.wav_path_rx.try_recv().ok().unwrap_or(self.wav_path);
+        // Only hand on the files the writers actually finished
+        let written: Vec<PathBuf> = self.wav_path_rx.try_iter().collect();
+        let (finalized, unfinished): (Vec<TrackOutput>, Vec<TrackOutput>) =
+            outputs.into_iter().partition(|track| written.contains(&track.wav_path));
+        for track in &unfinished {
+            log::warn!("WAV file was not finalized, skipping it: {}", track.wav_path.display());
+        }
         
         log::info!("Recording stopped");
         Some(RecordingOutput {
-            wav_path,
+            tracks: finalized,
             realtime_txt_path: self.realtime_txt_path,
             base_name: self.base_name,
         })
     }
 }
 
+/// Describe the audio a track lost to queue overflows, or `None` if it lost
+/// nothing.
+fn drop_summary(label: Option<&str>, dropped: &DroppedSamples) -> Option<String> {
+    let losses: Vec<String> = std::iter::once(("capture", dropped.capture))
+        .chain(dropped.consumers.iter().map(|(name, count)| (name.as_str(), *count)))
+        .filter(|&(_, count)| count > 0)
+        .map(|(name, count)| format!("{} {}", count, name))
+        .collect();
+    if losses.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{}Dropped audio: {} samples (capture and WAV gaps are written as silence)",
+        recognition::source_prefix(label),
+        losses.join(", ")
+    ))
+}
+
+/// Describe the audio published to consumers of a track after they had
+/// stopped (e.g. a recognizer that failed to start), or `None` if none did.
+fn stopped_summary(label: Option<&str>, dropped: &DroppedSamples) -> Option<String> {
+    let stopped: Vec<String> = dropped.stopped.iter()
+        .filter(|&&(_, count)| count > 0)
+        .map(|(name, count)| format!("{} {}", count, name))
+        .collect();
+    if stopped.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{}Not delivered after the consumer stopped early: {} samples",
+        recognition::source_prefix(label),
+        stopped.join(", ")
+    ))
+}
+
 /// Generate the banner lines for a given version/build pair.
 ///
 /// The returned vector contains the top border, two content lines, and
@@ -240,22 +434,58 @@ fn print_banner(version: &str, build: &str) {
     }
 }
 
-fn run_recording_mode(config: Arc<Config>) -> Result<()> {
-    // Always reset terminal state in case a previous run crashed while in raw mode
-    let _ = disable_raw_mode();
+/// A device to open for recording, as given by `--device` or config.toml.
+#[derive(Debug, PartialEq)]
+struct DeviceInput {
+    label: Option<String>,
+    selector: String,
+    processing: InputProcessing,
+}
 
-    // Restore terminal on panic so the shell is never left in raw mode
-    std::panic::set_hook(Box::new(|info| {
-        let _ = disable_raw_mode();
-        eprintln!("\r\n💥 panic: {}", info);
-    }));
+/// Work out which devices to record from: `--device` flags win over
+/// `[[inputs]]`, which win over `input_device`.  Empty means ask the user.
+/// A labeled `--device` takes the processing of the `[[inputs]]` entry with
+/// the same label; anything else uses the top-level settings.
+fn device_inputs(cli_devices: &[String], config: &Config) -> Result<Vec<DeviceInput>> {
+    if cli_devices.is_empty() {
+        if !config.inputs.is_empty() {
+            return Ok(config.inputs.iter().map(|i| DeviceInput {
+                label: Some(i.label.clone()),
+                selector: i.device.clone(),
+                processing: i.processing.clone(),
+            }).collect());
+        }
+        return Ok(config.input_device.iter().map(|d| DeviceInput {
+            label: None,
+            selector: d.clone(),
+            processing: config.processing.clone(),
+        }).collect());
+    }
 
-    let version = env!("CARGO_PKG_VERSION");
-    let build_num = env!("BUILD_NUMBER");
-    print_banner(version, build_num);
-    println!();
-    
-    // List available microphones
+    let processing_for = |label: &str| {
+        config.inputs.iter()
+            .find(|i| i.label == label)
+            .map_or_else(|| config.processing.clone(), |i| i.processing.clone())
+    };
+    let inputs: Vec<DeviceInput> = cli_devices.iter().map(|arg| match arg.split_once('=') {
+        Some((label, selector)) => DeviceInput {
+            label: Some(label.trim().to_string()),
+            selector: selector.trim().to_string(),
+            processing: processing_for(label.trim()),
+        },
+        None => DeviceInput { label: None, selector: arg.clone(), processing: config.processing.clone() },
+    }).collect();
+
+    if inputs.len() > 1 && inputs.iter().any(|i| i.label.is_none()) {
+        anyhow::bail!("Label each device when recording several, e.g. --device mic=1 --device monitor=2");
+    }
+    let labels: Vec<&str> = inputs.iter().filter_map(|i| i.label.as_deref()).collect();
+    config::validate_input_labels(&labels)?;
+    Ok(inputs)
+}
+
+/// List the input devices and ask the user to pick one on stdin.
+fn select_device_interactively() -> Result<cpal::Device> {
     println!("🎙️ Available microphones:");
     let devices = audio::list_input_devices()?;
     
@@ -285,6 +515,36 @@ fn run_recording_mode(config: Arc<Config>) -> Result<()> {
     
     let device = audio::select_device(index)?;
     println!("✔️  Selected: {}", devices[index].1);
+    Ok(device)
+}
+
+fn run_recording_mode(config: Arc<Config>, inputs: Vec<DeviceInput>) -> Result<()> {
+    // Always reset terminal state in case a previous run crashed while in raw mode
+    let _ = disable_raw_mode();
+
+    // Restore terminal on panic so the shell is never left in raw mode
+    std::panic::set_hook(Box::new(|info| {
+        let _ = disable_raw_mode();
+        eprintln!("\r\n💥 panic: {}", info);
+    }));
+
+    let version = env!("CARGO_PKG_VERSION");
+    let build_num = env!("BUILD_NUMBER");
+    print_banner(version, build_num);
+    println!();
+    
+    let devices: Vec<(DeviceInput, cpal::Device)> = if inputs.is_empty() {
+        let input = DeviceInput { label: None, selector: String::new(), processing: config.processing.clone() };
+        vec![(input, select_device_interactively()?)]
+    } else {
+        let mut devices = Vec::new();
+        for input in inputs {
+            let (device, name) = audio::resolve_device(&input.selector)?;
+            println!("✔️  Selected: {}{}", recognition::source_prefix(input.label.as_deref()), name);
+            devices.push((input, device));
+        }
+        devices
+    };
     println!();
     
     println!("Controls:");
@@ -338,67 +598,27 @@ fn run_recording_mode(config: Arc<Config>) -> Result<()> {
             InputCommand::StartRecording => {
                 if !is_recording {
                     println!("\n🔴 Recording started...");
-                    session = Some(RecordingSession::start(device.clone(), Arc::clone(&config))?);
+                    let mut inputs = Vec::new();
+                    for (input, device) in &devices {
+                        inputs.push(SessionInput {
+                            label: input.label.clone(),
+                            source: Box::new(source::CpalSource::new(device.clone())?),
+                            processing: input.processing.clone(),
+                        });
+                    }
+                    session = Some(RecordingSession::start(inputs, Arc::clone(&config))?);
                     is_recording = true;
                 }
             }
             InputCommand::StopRecording => {
                 if is_recording {
+                    status_line::end();
                     println!("\n⏹️  Stopping recording...");
                     if let Some(s) = session.take() {
                         let output = s.stop();
                         
-                        // Optionally run Whisper for accurate transcription (background thread)
-                        if config.enable_accurate_recognition {
-                            if let Some(output_ref) = output.as_ref() {
-                                let wav_path = output_ref.wav_path.clone();
-                                let cfg = Arc::clone(&config);
-                                println!("🔄 Starting accurate transcription with Whisper (background)...");
-                                std::thread::spawn(move || {
-                                    match whisper::transcribe_with_whisper(
-                                        &wav_path,
-                                        &cfg.whisper_model_path_accurate,
-                                        &cfg.output_directory,
-                                        &cfg,
-                                    ) {
-                                        Ok(_) => println!("✅ Accurate transcription completed"),
-                                        Err(e) => log::error!("Accurate transcription error: {}", e),
-                                    }
-                                });
-                            } else {
-                                log::warn!("Could not get WAV file path for accurate transcription");
-                            }
-                        }
-
-                        if let Some(output) = output {
-                            if config.ollama_enabled {
-                                let accurate_txt_path = PathBuf::from(&config.output_directory)
-                                    .join(format!("{}_accurate.txt", output.base_name));
-
-                                let summary_input = if accurate_txt_path.exists() {
-                                    accurate_txt_path
-                                } else {
-                                    output.realtime_txt_path
-                                };
-
-                                let summary_output = summary::build_summary_path(
-                                    &config.output_directory,
-                                    &output.base_name,
-                                    &config.summary_suffix,
-                                );
-
-                                let cfg = Arc::clone(&config);
-                                std::thread::spawn(move || {
-                                    if let Err(e) = summary::generate_summary_from_file(
-                                        &cfg,
-                                        &summary_input,
-                                        &summary_output,
-                                    ) {
-                                        log::error!("Summary generation error: {}", e);
-                                    }
-                                });
-                            }
-                        }
+                        // Detached: the menu stays responsive while Whisper runs
+                        start_post_processing(&config, output);
                     }
                     is_recording = false;
                     
@@ -415,6 +635,125 @@ fn run_recording_mode(config: Arc<Config>) -> Result<()> {
     Ok(())
 }
 
+/// Kick off accurate transcription and summary generation for a finished
+/// recording on a background thread.  The summary comes last so it can use
+/// the accurate transcripts.
+fn start_post_processing(
+    config: &Arc<Config>,
+    output: Option<RecordingOutput>,
+) -> Vec<std::thread::JoinHandle<()>> {
+    let Some(output) = output else {
+        if config.enable_accurate_recognition {
+            log::warn!("Could not get WAV file path for accurate transcription");
+        }
+        return Vec::new();
+    };
+    if !config.enable_accurate_recognition && !config.ollama_enabled {
+        return Vec::new();
+    }
+    
+    if config.enable_accurate_recognition {
+        println!("🔄 Starting accurate transcription with Whisper (background)...");
+    }
+    let cfg = Arc::clone(config);
+    vec![std::thread::spawn(move || {
+        // Optionally run Whisper for accurate transcription, one track at a
+        // time so only one Whisper model is in memory
+        if cfg.enable_accurate_recognition {
+            for track in &output.tracks {
+                match whisper::transcribe_with_whisper(
+                    &track.wav_path,
+                    &cfg.whisper_model_path_accurate,
+                    &cfg.output_directory,
+                    &cfg,
+                ) {
+                    Ok(_) => println!("✅ Accurate transcription completed"),
+                    Err(e) => log::error!("Accurate transcription error: {}", e),
+                }
+            }
+        }
+        
+        if cfg.ollama_enabled {
+            let summary_output = summary::build_summary_path(
+                &cfg.output_directory,
+                &output.base_name,
+                &cfg.summary_suffix,
+            );
+            let inputs = summary_inputs(&cfg, &output);
+            if let Err(e) = summary::generate_summary_from_files(&cfg, &inputs, &summary_output) {
+                log::error!("Summary generation error: {}", e);
+            }
+        }
+    })]
+}
+
+/// The transcripts to summarize: every track's accurate transcript when they
+/// all have one, otherwise the shared real-time transcript.
+fn summary_inputs(config: &Config, output: &RecordingOutput) -> Vec<(Option<String>, PathBuf)> {
+    let accurate: Vec<(Option<String>, PathBuf)> = output.tracks.iter()
+        .map(|track| {
+            let path = whisper::accurate_transcript_path(&config.output_directory, &track.wav_path);
+            (track.label.clone(), path)
+        })
+        .collect();
+    if !accurate.is_empty() && accurate.iter().all(|(_, path)| path.exists()) {
+        accurate
+    } else {
+        vec![(None, output.realtime_txt_path.clone())]
+    }
+}
+
+/// Build the non-microphone source requested on the command line, if any.
+fn source_from_cli(cli: &Cli) -> Result<Option<Box<dyn source::AudioSource>>> {
+    if let Some(path) = &cli.input_file {
+        return Ok(Some(Box::new(source::WavFileSource::new(path, !cli.fast)?)));
+    }
+    if cli.input_stdin {
+        let format = source::SourceFormat {
+            sample_rate: cli.stdin_rate,
+            channels: cli.stdin_channels,
+        };
+        return Ok(Some(Box::new(source::RawPcmSource::stdin(format, cli.stdin_format))));
+    }
+    if let Some(spec) = &cli.input_synth {
+        let signal: source::Signal = spec.parse()?;
+        let format = source::SourceFormat { sample_rate: 48000, channels: 1 };
+        let duration = std::time::Duration::from_secs_f32(cli.duration.max(0.0));
+        return Ok(Some(Box::new(source::SyntheticSource::new(signal, format, Some(duration), true))));
+    }
+    Ok(None)
+}
+
+/// Record from a file, stdin or synthetic source without keyboard control:
+/// recording starts immediately and stops at end of input or on Ctrl+C.
+fn run_source_mode(config: Arc<Config>, source: Box<dyn source::AudioSource>) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || {
+        r.store(false, Ordering::Relaxed);
+    }).expect("Error setting Ctrl+C handler");
+
+    println!("🔴 Recording from {}...", source.name());
+    let input = SessionInput { label: None, source, processing: config.processing.clone() };
+    let session = RecordingSession::start(vec![input], Arc::clone(&config))?;
+    while running.load(Ordering::Relaxed) && !session.is_finished() {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    println!("⏹️  Stopping recording...");
+    let output = session.stop();
+    if let Some(output) = output.as_ref() {
+        for track in &output.tracks {
+            println!("✅ Recording saved: {}", track.wav_path.display());
+        }
+    }
+    for handle in start_post_processing(&config, output) {
+        let _ = handle.join();
+    }
+
+    Ok(())
+}
+
 fn run_accurate_mode(config: Arc<Config>, wav_file: String) -> Result<()> {
     println!("Running accurate transcription on: {}", wav_file);
     
@@ -439,6 +778,58 @@ fn run_accurate_mode(config: Arc<Config>, wav_file: String) -> Result<()> {
     Ok(())
 }
 
+/// Parse a duration such as "10s", "500ms", "2m" or plain seconds ("1.5").
+fn parse_duration_secs(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let (number, scale) = if let Some(n) = text.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = text.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = text.strip_suffix('m') {
+        (n, 60.0)
+    } else {
+        (text, 1.0)
+    };
+    let secs = number
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("invalid duration \"{}\" (use e.g. 10s, 500ms or 2m)", text))?
+        * scale;
+    if !(secs > 0.0 && secs.is_finite()) {
+        return Err(format!("duration must be positive: \"{}\"", text));
+    }
+    Ok(secs)
+}
+
+fn run_analyze_mode(config: Arc<Config>, wav_file: String, window: Option<f64>, json: bool) -> Result<()> {
+    let wav_path = if PathBuf::from(&wav_file).exists() {
+        PathBuf::from(&wav_file)
+    } else {
+        PathBuf::from(&config.output_directory).join(&wav_file)
+    };
+
+    if !wav_path.exists() {
+        anyhow::bail!("WAV file not found: {}", wav_path.display());
+    }
+
+    let audio = analysis::load_wav(&wav_path)?;
+    let report = analysis::quality_report(
+        &wav_path.display().to_string(),
+        &audio,
+        config.audio_gain,
+        window,
+        config.vad_threshold_db,
+    );
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.to_text());
+    }
+
+    Ok(())
+}
+
 fn run_summary_mode(config: Arc<Config>, txt_file: String) -> Result<()> {
     println!("Generating summary for: {}", txt_file);
 
@@ -469,6 +860,92 @@ fn run_summary_mode(config: Arc<Config>, txt_file: String) -> Result<()> {
     Ok(())
 }
 
+fn run_calibrate_mode(config: Arc<Config>, cli_devices: &[String], seconds: f32) -> Result<()> {
+    let (device, processing) = match device_inputs(cli_devices, &config)?.as_slice() {
+        [] => (select_device_interactively()?, config.processing.clone()),
+        [input] => {
+            let (device, name) = audio::resolve_device(&input.selector)?;
+            println!("✔️  Selected: {}", name);
+            (device, input.processing.clone())
+        }
+        _ => anyhow::bail!("Calibrate one device at a time: pass a single --device"),
+    };
+    if config.gain_mode == "auto" {
+        println!("ℹ️  gain_mode is \"auto\"; measuring with the fixed audio_gain it starts from.");
+    }
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || {
+        r.store(false, Ordering::Relaxed);
+    }).expect("Error setting Ctrl+C handler");
+
+    println!();
+    println!("📖 Read this passage aloud at your normal speaking volume:");
+    println!();
+    println!("{}", calibrate::PASSAGE);
+    println!();
+    print!("Press Enter to start recording ({:.0} s)... ", seconds);
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+
+    println!("🔴 Recording... (Ctrl+C to stop early)");
+    let source = Box::new(source::CpalSource::new(device)?);
+    let samples = calibrate::record(source, &processing, &config, seconds, &running)?;
+    println!("⏹️  Recorded {:.1} s", samples.len() as f32 / config.sample_rate as f32);
+    if samples.is_empty() {
+        anyhow::bail!("Nothing was recorded; check the device");
+    }
+
+    let analysis = analysis::analyze(&samples, config.sample_rate, config.audio_gain);
+    analysis.log();
+    let recommended = calibrate::config_gain(analysis.recommended_gain);
+    println!();
+    if (recommended - config.audio_gain).abs() < 0.05 {
+        println!("✅ audio_gain = {:.1} is already right for this device.", config.audio_gain);
+        return Ok(());
+    }
+    println!("🎚️  Recommended audio_gain: {:.1} (currently {:.1})", recommended, config.audio_gain);
+    if recommended >= analysis::MAX_GAIN {
+        println!(
+            "⚠️  {:.1} is the highest audio_gain pstt allows, and the input may need more.  \
+             Raise the input gain of the device or interface itself.",
+            analysis::MAX_GAIN
+        );
+    }
+    print!("Write audio_gain = {:.1} to {}? [y/N] ", recommended, config::CONFIG_PATH);
+    std::io::Write::flush(&mut std::io::stdout())?;
+    line.clear();
+    std::io::stdin().read_line(&mut line)?;
+    if !line.trim().eq_ignore_ascii_case("y") {
+        println!("Left {} unchanged.", config::CONFIG_PATH);
+        return Ok(());
+    }
+
+    let content = std::fs::read_to_string(config::CONFIG_PATH)
+        .with_context(|| format!("Failed to read {}", config::CONFIG_PATH))?;
+    std::fs::write(config::CONFIG_PATH, config::set_audio_gain(&content, recommended)?)
+        .with_context(|| format!("Failed to write {}", config::CONFIG_PATH))?;
+    println!("✅ Saved. Run `pstt calibrate` again to check the new level.");
+
+    Ok(())
+}
+
+fn run_devices_mode(json: bool) -> Result<()> {
+    let devices = audio::list_input_device_capabilities()?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&devices)?);
+    } else if devices.is_empty() {
+        println!("No input devices found.");
+    } else {
+        print!("{}", audio::format_device_capabilities(&devices));
+    }
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
     // Initialize logger
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
@@ -489,6 +966,11 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    // Listing devices must work before config.toml has been written
+    if let Some(Commands::Devices { json }) = cli.command {
+        return run_devices_mode(json);
+    }
+
     let config = Arc::new(Config::load()?);
     
     match cli.command {
@@ -498,8 +980,20 @@ fn main() -> Result<()> {
         Some(Commands::Summary { txt_file }) => {
             run_summary_mode(config, txt_file)?;
         }
+        Some(Commands::Analyze { wav_file, window, json }) => {
+            run_analyze_mode(config, wav_file, window, json)?;
+        }
+        Some(Commands::Calibrate { seconds }) => {
+            run_calibrate_mode(config, &cli.device, seconds)?;
+        }
+        Some(Commands::Devices { .. }) => unreachable!("handled before config load"),
         None => {
-            run_recording_mode(config)?;
+            if let Some(source) = source_from_cli(&cli)? {
+                run_source_mode(config, source)?;
+            } else {
+                let inputs = device_inputs(&cli.device, &config)?;
+                run_recording_mode(config, inputs)?;
+            }
         }
     }
     
@@ -509,7 +1003,11 @@ fn main() -> Result<()> {
 // compile-time sanity check for build number
 #[cfg(test)]
 mod tests {
-    use super::{banner_lines};
+    use super::{banner_lines, device_inputs, drop_summary, stopped_summary, parse_duration_secs, Config, DeviceInput, DroppedSamples, InputProcessing, RecordingOutput, RecordingSession, SessionInput, TrackOutput, summary_inputs, Arc};
+    use crate::source::{Signal, SourceFormat, SyntheticSource};
+    use crate::config::fixture::parse_with;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
 
     #[test]
     fn build_number_is_numeric() {
@@ -517,6 +1015,16 @@ mod tests {
         assert!(build > 0, "build number should be positive");
     }
 
+    #[test]
+    fn durations_accept_common_units() {
+        assert_eq!(parse_duration_secs("10s"), Ok(10.0));
+        assert_eq!(parse_duration_secs("500ms"), Ok(0.5));
+        assert_eq!(parse_duration_secs("2m"), Ok(120.0));
+        assert_eq!(parse_duration_secs("1.5"), Ok(1.5));
+        assert!(parse_duration_secs("0s").is_err());
+        assert!(parse_duration_secs("ten").is_err());
+    }
+
     #[test]
     fn banner_width_adapts() {
         // helper to calculate expected total width (chars) including borders/spaces
@@ -555,4 +1063,262 @@ mod tests {
         assert_eq!(lines3[0].chars().count(), exp3);
         assert!(lines3[2].contains(long_build));
     }
+
+    fn test_config(dir: &Path) -> Config {
+        let mut config = parse_with("");
+        config.output_directory = dir.display().to_string();
+        // No recognizer model: the real-time thread fails, the WAV path must not care
+        config.vosk_model_path = Some("./does-not-exist".to_string());
+        config
+    }
+
+    fn tone(frequency: f32, sample_rate: u32, channels: u16) -> Box<SyntheticSource> {
+        Box::new(SyntheticSource::new(
+            Signal::Tone { frequency, amplitude: 0.5 },
+            SourceFormat { sample_rate, channels },
+            Some(Duration::from_secs(1)),
+            false,
+        ))
+    }
+
+    /// Record `inputs` to completion and return the WAV paths.
+    fn record(inputs: Vec<SessionInput>, dir: &Path) -> Vec<PathBuf> {
+        let session = RecordingSession::start(inputs, Arc::new(test_config(dir))).unwrap();
+        while !session.is_finished() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let output = session.stop().expect("no recording output");
+        output.tracks.into_iter().map(|track| track.wav_path).collect()
+    }
+
+    /// Sample count and number of rising zero crossings of a 16 kHz mono WAV.
+    fn wav_length_and_cycles(path: &Path) -> (usize, usize) {
+        let mut reader = hound::WavReader::open(path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 16000);
+        assert_eq!(reader.spec().channels, 1);
+        let samples: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+        let rising = samples.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count();
+        (samples.len(), rising)
+    }
+
+    #[test]
+    fn synthetic_source_records_wav_end_to_end() {
+        let dir = std::env::temp_dir().join(format!("pstt-e2e-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let input = SessionInput { label: None, source: tone(440.0, 44100, 2), processing: InputProcessing::default() };
+        let wav_paths = record(vec![input], &dir);
+        assert_eq!(wav_paths.len(), 1);
+        let (len, cycles) = wav_length_and_cycles(&wav_paths[0]);
+        let stem = wav_paths[0].file_stem().unwrap().to_string_lossy().to_string();
+        let stats_path = wav_paths[0].with_file_name(format!("{}_audio.json", stem));
+        let stats: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&stats_path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert!((len as i64 - 16000).abs() < 1024, "got {} samples", len);
+        assert!((cycles as i64 - 440).abs() <= 5, "got {} cycles", cycles);
+
+        // Level statistics cover the whole input, at its own rate
+        assert!(stats["samples"].as_u64().unwrap() >= len as u64);
+        assert!(stats["rms"].as_f64().unwrap() > 0.1);
+        assert_eq!(stats["minutes"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn multiple_inputs_record_separate_tracks() {
+        let dir = std::env::temp_dir().join(format!("pstt-multi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let wav_paths = record(vec![
+            SessionInput { label: Some("mic".into()), source: tone(440.0, 48000, 1), processing: InputProcessing::default() },
+            SessionInput { label: Some("monitor".into()), source: tone(880.0, 44100, 2), processing: InputProcessing::default() },
+        ], &dir);
+        let names: Vec<String> = wav_paths.iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        let tracks: Vec<(usize, usize)> = wav_paths.iter().map(|p| wav_length_and_cycles(p)).collect();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert!(names[0].ends_with("_mic.wav"), "{}", names[0]);
+        assert!(names[1].ends_with("_monitor.wav"), "{}", names[1]);
+        for (&(len, cycles), expected) in tracks.iter().zip([440, 880]) {
+            assert!((len as i64 - 16000).abs() < 1024, "got {} samples", len);
+            assert!((cycles as i64 - expected).abs() <= 5, "got {} cycles, expected {}", cycles, expected);
+        }
+    }
+
+    #[test]
+    fn each_input_is_mixed_by_its_own_channel_selection() {
+        let dir = std::env::temp_dir().join(format!("pstt-per-input-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let right_only = InputProcessing { input_channels: Some(vec![1]), ..Default::default() };
+        let inputs = |mic: InputProcessing| vec![
+            SessionInput { label: Some("mic".into()), source: tone(440.0, 48000, 1), processing: mic },
+            SessionInput { label: Some("monitor".into()), source: tone(880.0, 44100, 2), processing: right_only.clone() },
+        ];
+
+        // The stereo monitor's selection doesn't fit the mono mic
+        assert!(RecordingSession::start(inputs(right_only.clone()), Arc::new(test_config(&dir))).is_err());
+
+        let wav_paths = record(inputs(InputProcessing::default()), &dir);
+        let tracks: Vec<(usize, usize)> = wav_paths.iter().map(|p| wav_length_and_cycles(p)).collect();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(tracks.len(), 2);
+        assert!((tracks[1].1 as i64 - 880).abs() <= 5, "got {} cycles", tracks[1].1);
+    }
+
+    #[test]
+    fn failed_writers_leave_their_track_out_of_the_output() {
+        let dir = std::env::temp_dir().join(format!("pstt-unfinished-{}", std::process::id()));
+        // A directory where the monitor WAV goes makes its writer fail; the
+        // file name starts with the session's start time
+        let now = chrono::Local::now();
+        for s in 0..5 {
+            let base = (now + chrono::Duration::seconds(s)).format("%Y-%m-%d_%H-%M-%S");
+            std::fs::create_dir_all(dir.join(format!("{}_monitor.wav", base))).unwrap();
+        }
+
+        let wav_paths = record(vec![
+            SessionInput { label: Some("mic".into()), source: tone(440.0, 48000, 1), processing: InputProcessing::default() },
+            SessionInput { label: Some("monitor".into()), source: tone(880.0, 44100, 2), processing: InputProcessing::default() },
+        ], &dir);
+        let (len, _) = wav_length_and_cycles(&wav_paths[0]);
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(wav_paths.len(), 1, "{:?}", wav_paths);
+        assert!(wav_paths[0].to_string_lossy().ends_with("_mic.wav"), "{:?}", wav_paths);
+        assert!((len as i64 - 16000).abs() < 1024, "got {} samples", len);
+    }
+
+    #[test]
+    fn stopping_mid_stream_keeps_every_captured_sample() {
+        let dir = std::env::temp_dir().join(format!("pstt-stop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Paced to the wall clock and endless, so the stop lands mid-stream
+        let source = Box::new(SyntheticSource::new(
+            Signal::Tone { frequency: 440.0, amplitude: 0.5 },
+            SourceFormat { sample_rate: 44100, channels: 2 },
+            None,
+            true,
+        ));
+        let session = RecordingSession::start(
+            vec![SessionInput { label: None, source, processing: InputProcessing::default() }],
+            Arc::new(test_config(&dir)),
+        ).unwrap();
+        let raw = Arc::clone(&session.tracks[0].pipeline.raw_queue);
+        std::thread::sleep(Duration::from_millis(300));
+        let output = session.stop().expect("no recording output");
+        let (len, _) = wav_length_and_cycles(&output.tracks[0].wav_path);
+        std::fs::remove_dir_all(&dir).ok();
+
+        // Every captured frame reaches the WAV, resampled to 16 kHz
+        let frames = raw.captured() / 2;
+        assert!(frames > 0);
+        assert_eq!(raw.overruns(), 0);
+        assert_eq!(len as u64, (frames * 16000 + 22050) / 44100, "{} frames captured", frames);
+    }
+
+    #[test]
+    fn summary_uses_each_tracks_accurate_transcript() {
+        let dir = std::env::temp_dir().join(format!("pstt-summary-inputs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = test_config(&dir);
+        let output = RecordingOutput {
+            tracks: vec![
+                TrackOutput { label: Some("mic".into()), wav_path: dir.join("2024-01-01_10-00-00_mic.wav") },
+                TrackOutput { label: Some("monitor".into()), wav_path: dir.join("2024-01-01_10-00-00_monitor.wav") },
+            ],
+            realtime_txt_path: dir.join("2024-01-01_10-00-00.txt"),
+            base_name: "2024-01-01_10-00-00".into(),
+        };
+
+        // Until every track has one, the real-time transcript stands in
+        std::fs::write(dir.join("2024-01-01_10-00-00_mic_accurate.txt"), "hello").unwrap();
+        let fallback = summary_inputs(&config, &output);
+        std::fs::write(dir.join("2024-01-01_10-00-00_monitor_accurate.txt"), "hi").unwrap();
+        let accurate = summary_inputs(&config, &output);
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(fallback, vec![(None, dir.join("2024-01-01_10-00-00.txt"))]);
+        assert_eq!(accurate, vec![
+            (Some("mic".to_string()), dir.join("2024-01-01_10-00-00_mic_accurate.txt")),
+            (Some("monitor".to_string()), dir.join("2024-01-01_10-00-00_monitor_accurate.txt")),
+        ]);
+    }
+
+    #[test]
+    fn drop_summary_lists_only_tracks_that_lost_audio() {
+        let consumers = vec![("WAV".to_string(), 0), ("real-time".to_string(), 0)];
+        let nothing = DroppedSamples { capture: 0, consumers: consumers.clone(), stopped: consumers.clone() };
+        assert_eq!(drop_summary(Some("mic"), &nothing), None);
+        assert_eq!(stopped_summary(Some("mic"), &nothing), None);
+        let consumers = vec![("WAV".to_string(), 160), ("real-time".to_string(), 3200), ("streamer".to_string(), 0)];
+        let line = drop_summary(Some("mic"), &DroppedSamples { capture: 0, consumers, stopped: Vec::new() }).unwrap();
+        assert!(line.starts_with("[mic] "), "{}", line);
+        assert!(line.contains("160 WAV, 3200 real-time samples"), "{}", line);
+        assert!(!line.contains("capture,") && !line.contains("streamer"), "{}", line);
+    }
+
+    #[test]
+    fn a_failed_recognizer_is_not_counted_as_overflow() {
+        let dir = std::env::temp_dir().join(format!("pstt-failed-recognizer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // The test config has no recognizer model, so the real-time thread
+        // exits right away while the WAV keeps recording
+        let input = SessionInput { label: Some("mic".into()), source: tone(440.0, 16000, 1), processing: InputProcessing::default() };
+        let session = RecordingSession::start(vec![input], Arc::new(test_config(&dir))).unwrap();
+        let bus = Arc::clone(&session.tracks[0].pipeline.bus);
+        session.stop().expect("no recording output");
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(bus.dropped(), vec![("WAV".to_string(), 0), ("real-time".to_string(), 0)]);
+        let stopped = bus.discarded();
+        assert_eq!(stopped[0], ("WAV".to_string(), 0));
+        assert!(stopped[1].1 > 0, "{:?}", stopped);
+        let dropped = DroppedSamples { capture: 0, consumers: bus.dropped(), stopped };
+        assert_eq!(drop_summary(Some("mic"), &dropped), None);
+        let line = stopped_summary(Some("mic"), &dropped).unwrap();
+        assert!(line.starts_with("[mic] ") && line.contains(" real-time"), "{}", line);
+    }
+
+    #[test]
+    fn device_flags_take_optional_labels() {
+        let dir = std::env::temp_dir();
+        let mut config = test_config(&dir);
+
+        let single = device_inputs(&["USB Headset".into()], &config).unwrap();
+        let processing = InputProcessing::default();
+        assert_eq!(single, vec![DeviceInput { label: None, selector: "USB Headset".into(), processing }]);
+
+        let several = device_inputs(&["mic=1".into(), "monitor=Monitor of".into()], &config).unwrap();
+        let processing = InputProcessing::default();
+        assert_eq!(several[1], DeviceInput { label: Some("monitor".into()), selector: "Monitor of".into(), processing });
+
+        assert!(device_inputs(&["mic=1".into(), "2".into()], &config).is_err());
+        assert!(device_inputs(&["mic=1".into(), "mic=2".into()], &config).is_err());
+
+        // Without flags the config decides; nothing configured means the menu
+        assert!(device_inputs(&[], &config).unwrap().is_empty());
+        config.input_device = Some("2".into());
+        assert_eq!(device_inputs(&[], &config).unwrap()[0].selector, "2");
+    }
+
+    #[test]
+    fn labeled_devices_use_their_inputs_entry() {
+        let config = parse_with(r#"
+            [[inputs]]
+            label = "monitor"
+            device = "Monitor of"
+            input_channels = [1]
+        "#);
+        let right_only = InputProcessing { input_channels: Some(vec![1]), ..Default::default() };
+
+        assert_eq!(device_inputs(&[], &config).unwrap()[0].processing, right_only);
+        let flags = device_inputs(&["mic=1".into(), "monitor=2".into()], &config).unwrap();
+        assert_eq!(flags[0].processing, InputProcessing::default());
+        assert_eq!(flags[1].processing, right_only);
+    }
 }
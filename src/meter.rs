@@ -0,0 +1,139 @@
+//! Live input level meter.
+//!
+//! The resampler feeds the post-gain signal in; every 100 ms the meter
+//! reports that window's RMS and peak in dBFS.  Anything at or above full
+//! scale would have clipped without the limiter, so it raises a clip warning
+//! that stays up for a second to be noticeable at 10 updates per second.
+
+use crate::recognition::source_prefix;
+
+const WINDOW_MS: u32 = 100;
+/// Windows the clip warning stays visible after the last clipped sample.
+const CLIP_HOLD_WINDOWS: u32 = 10;
+/// Bottom of the meter scale; quieter levels are reported as this.
+pub const FLOOR_DB: f32 = -60.0;
+const BAR_CELLS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    pub rms_db: f32,
+    pub peak_db: f32,
+    /// A sample reached full scale in this window or the last second.
+    pub clipping: bool,
+}
+
+pub struct LevelMeter {
+    window_len: usize,
+    fill: usize,
+    sum_squares: f64,
+    peak: f32,
+    clip_hold: u32,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            window_len: (sample_rate * WINDOW_MS / 1000).max(1) as usize,
+            fill: 0,
+            sum_squares: 0.0,
+            peak: 0.0,
+            clip_hold: 0,
+        }
+    }
+
+    /// Measure `samples`; returns the level of the last window they completed.
+    pub fn process(&mut self, samples: &[f32]) -> Option<Level> {
+        let mut level = None;
+        for &s in samples {
+            self.sum_squares += (s * s) as f64;
+            self.peak = self.peak.max(s.abs());
+            self.fill += 1;
+            if self.fill == self.window_len {
+                level = Some(self.finish_window());
+            }
+        }
+        level
+    }
+
+    fn finish_window(&mut self) -> Level {
+        let rms = (self.sum_squares / self.window_len as f64).sqrt() as f32;
+        if self.peak >= 1.0 {
+            self.clip_hold = CLIP_HOLD_WINDOWS;
+        } else {
+            self.clip_hold = self.clip_hold.saturating_sub(1);
+        }
+        let level = Level {
+            rms_db: to_db(rms),
+            peak_db: to_db(self.peak),
+            clipping: self.clip_hold > 0,
+        };
+        self.fill = 0;
+        self.sum_squares = 0.0;
+        self.peak = 0.0;
+        level
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.max(1e-9).log10()).max(FLOOR_DB)
+}
+
+/// One-line meter: RMS bar with a peak tick, both readings, and the clip flag.
+pub fn render(level: &Level, label: Option<&str>) -> String {
+    let cell = |db: f32| (((db - FLOOR_DB) / -FLOOR_DB) * BAR_CELLS as f32).round() as usize;
+    let filled = cell(level.rms_db).min(BAR_CELLS);
+    let peak = cell(level.peak_db).min(BAR_CELLS);
+    let bar: String = (0..BAR_CELLS)
+        .map(|i| if i < filled { '█' } else if i + 1 == peak { '│' } else { '░' })
+        .collect();
+    format!(
+        "{}{} {:>5.1} dB pk {:>5.1}{}",
+        source_prefix(label),
+        bar,
+        level.rms_db,
+        level.peak_db,
+        if level.clipping { " ⚠️ CLIP" } else { "" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn sine(amplitude: f32, seconds: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 250.0 * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn reports_rms_and_peak_every_100ms() {
+        let mut meter = LevelMeter::new(RATE);
+        assert!(meter.process(&sine(0.5, 0.05)).is_none());
+        let level = meter.process(&sine(0.5, 0.05)).unwrap();
+        // A sine's RMS sits 3 dB below its peak
+        assert!((level.peak_db - -6.02).abs() < 0.1, "{:?}", level);
+        assert!((level.rms_db - -9.03).abs() < 0.1, "{:?}", level);
+        assert!(!level.clipping);
+
+        let silent = meter.process(&[0.0; 1600]).unwrap();
+        assert_eq!(silent.rms_db, FLOOR_DB);
+    }
+
+    #[test]
+    fn clip_warning_is_held_for_a_second() {
+        let mut meter = LevelMeter::new(RATE);
+        let loud = meter.process(&sine(1.5, 0.1)).unwrap();
+        assert!(loud.clipping);
+        assert!(render(&loud, Some("mic")).starts_with("[mic] "));
+        assert!(render(&loud, None).contains("CLIP"));
+
+        let quiet = meter.process(&sine(0.1, 0.5)).unwrap();
+        assert!(quiet.clipping);
+        let quiet = meter.process(&sine(0.1, 0.6)).unwrap();
+        assert!(!quiet.clipping);
+        assert!(!render(&quiet, None).contains("CLIP"));
+    }
+}
@@ -2,9 +2,9 @@ use vosk::{Model, Recognizer};
 use anyhow::Result;
 use std::sync::mpsc;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use crate::buffers::BlockingQueue;
+use crate::buffers::{BlockingQueue, CloseOnDrop, PopError};
 use crate::config::Config;
+use crate::status_line;
 use chrono::Local;
 use std::time::Duration;
 
@@ -15,6 +15,13 @@ pub struct RecognizedText {
     pub text: String,
     pub timestamp: chrono::DateTime<chrono::Local>,
     pub is_final: bool,
+    /// Label of the input the text came from when recording several inputs.
+    pub source: Option<String>,
+}
+
+/// `"[label] "` for a labeled input, empty for a single unlabeled one.
+pub fn source_prefix(source: Option<&str>) -> String {
+    source.map(|s| format!("[{}] ", s)).unwrap_or_default()
 }
 
 // ── Engine abstraction ────────────────────────────────────────────────────────
@@ -38,7 +45,8 @@ pub trait RealtimeRecognizer {
 
 // ── Factory ───────────────────────────────────────────────────────────────────
 
-/// Create the engine selected by `config.realtime_engine`.
+/// Create the engine selected by `config.realtime_engine`.  `source` labels
+/// every emitted line when several inputs are recorded at once.
 ///
 /// Returns an error if:
 /// - the engine name is unknown, or
@@ -47,6 +55,7 @@ pub trait RealtimeRecognizer {
 pub fn create_realtime_recognizer(
     config: &Config,
     text_sender: mpsc::Sender<RecognizedText>,
+    source: Option<String>,
 ) -> Result<Box<dyn RealtimeRecognizer>> {
     match config.realtime_engine.as_str() {
         "vosk" => {
@@ -60,6 +69,7 @@ pub fn create_realtime_recognizer(
                 path,
                 config.sample_rate as f32,
                 text_sender,
+                source,
             )?))
         }
         "sherpa-onnx" => {
@@ -73,6 +83,7 @@ pub fn create_realtime_recognizer(
                     &config.sherpa_tokens,
                     config.sample_rate,
                     text_sender,
+                    source,
                 )?))
             }
             #[cfg(not(feature = "sherpa-engine"))]
@@ -96,6 +107,7 @@ pub fn create_realtime_recognizer(
 pub struct VoskRecognizer {
     recognizer: Recognizer,
     text_sender: mpsc::Sender<RecognizedText>,
+    source: Option<String>,
 }
 
 impl VoskRecognizer {
@@ -103,6 +115,7 @@ impl VoskRecognizer {
         model_path: &str,
         sample_rate: f32,
         text_sender: mpsc::Sender<RecognizedText>,
+        source: Option<String>,
     ) -> Result<Self> {
         log::info!("Loading Vosk model from: {}", model_path);
         let model = Model::new(model_path)
@@ -116,7 +129,7 @@ impl VoskRecognizer {
 
         log::info!("Vosk model loaded successfully (sample_rate: {} Hz)", sample_rate);
 
-        Ok(Self { recognizer, text_sender })
+        Ok(Self { recognizer, text_sender, source })
     }
 }
 
@@ -137,11 +150,12 @@ impl RealtimeRecognizer for VoskRecognizer {
                     if let Some(single) = self.recognizer.result().single() {
                         let text = single.text;
                         if !text.is_empty() {
-                            println!("🎤 Recognized: {}", text);
+                            status_line::print_result(&format!("🎤 {}Recognized: {}", source_prefix(self.source.as_deref()), text));
                             let _ = self.text_sender.send(RecognizedText {
                                 text: text.to_string(),
                                 timestamp: Local::now(),
                                 is_final: false,
+                                source: self.source.clone(),
                             });
                         }
                     }
@@ -163,11 +177,12 @@ impl RealtimeRecognizer for VoskRecognizer {
         if let Some(single) = self.recognizer.final_result().single() {
             let text = single.text;
             if !text.is_empty() {
-                println!("🎤 Final: {}", text);
+                status_line::print_result(&format!("🎤 {}Final: {}", source_prefix(self.source.as_deref()), text));
                 let _ = self.text_sender.send(RecognizedText {
                     text: text.to_string(),
                     timestamp: Local::now(),
                     is_final: true,
+                    source: self.source.clone(),
                 });
             }
         }
@@ -180,26 +195,27 @@ impl RealtimeRecognizer for VoskRecognizer {
 pub fn realtime_recognition_thread(
     resampled_queue: Arc<BlockingQueue<f32>>,
     text_sender: mpsc::Sender<RecognizedText>,
+    source: Option<String>,
     config: Arc<Config>,
-    stop_signal: Arc<AtomicBool>,
 ) -> Result<()> {
     log::info!("Real-time recognition thread started (engine: {})", config.realtime_engine);
-
-    let mut recognizer = create_realtime_recognizer(&config, text_sender)?;
-
-    while !stop_signal.load(Ordering::Relaxed) {
-        if let Some(samples) = resampled_queue.try_pop_batch(4096) {
-            recognizer.process_audio(&samples)?;
-        } else {
-            std::thread::sleep(Duration::from_millis(50));
+    // Stop the bus filling the queue if the recognizer fails
+    let _close = CloseOnDrop(Arc::clone(&resampled_queue));
+
+    let mut recognizer = create_realtime_recognizer(&config, text_sender, source)?;
+
+    // Recognize until the resampler closes the queue, then finish what is
+    // buffered.  Dropped audio is skipped, not padded.
+    let mut samples = Vec::with_capacity(4096);
+    loop {
+        match resampled_queue.pop_into_timeout(&mut samples, 4096, Duration::from_millis(500)) {
+            Ok(_) if samples.is_empty() => continue,
+            Ok(_) => recognizer.process_audio(&samples)?,
+            Err(PopError::Timeout) => continue,
+            Err(PopError::Closed) => break,
         }
     }
 
-    // Drain any remaining buffered samples
-    while let Some(samples) = resampled_queue.try_pop_batch(4096) {
-        recognizer.process_audio(&samples)?;
-    }
-
     recognizer.finalize()?;
     log::info!("Real-time recognition thread finished");
 
@@ -1,215 +1,1125 @@
-use rubato::{Resampler, SincFixedIn, SincInterpolationType, SincInterpolationParameters, WindowFunction};
-use anyhow::Result;
+use rubato::{
+    FastFixedIn, FftFixedIn, PolynomialDegree, SincFixedIn, SincInterpolationParameters,
+    SincInterpolationType, VecResampler, WindowFunction,
+};
+use anyhow::{Context, Result};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use crate::buffers::BlockingQueue;
-use crate::config::Config;
+use crate::analysis::{AudioAnalyzer, MAX_GAIN, TARGET_RMS};
+use crate::buffers::{CaptureRing, PopError};
+use crate::bus::{AudioBus, Tap};
+use crate::config::{Config, InputProcessing};
+use crate::denoise::{NoiseSuppressor, NoiseTarget};
+use crate::dsp::DspChain;
+use crate::limiter::SoftLimiter;
+use crate::meter::LevelMeter;
+use crate::source::SourceFormat;
+use crate::status_line;
+use crate::vad::{VadSettings, VoiceActivityGate};
+use std::path::PathBuf;
 use std::time::Duration;
 
+/// `resampler_quality`: which rubato resampler converts to `sample_rate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResamplerQuality {
+    /// Septic polynomial interpolation.  Cheapest, but without an
+    /// anti-aliasing filter: when downsampling, anything above the output
+    /// Nyquist frequency folds back into the audio.
+    Fast,
+    /// FFT-based synchronous resampler with anti-aliasing.
+    Balanced,
+    /// 256-tap windowed sinc with 256x oversampling.
+    Best,
+}
+
+impl ResamplerQuality {
+    pub fn from_config(config: &Config) -> Self {
+        match config.resampler_quality.as_str() {
+            "fast" => Self::Fast,
+            "balanced" => Self::Balanced,
+            _ => Self::Best,
+        }
+    }
+}
+
+/// Converts a mono stream to the output rate with the output aligned to the
+/// input: the filter's start-up delay is trimmed, and `flush` ends the
+/// stream on exactly `input × output_rate / input_rate` samples.
 pub struct AudioResampler {
-    resampler: SincFixedIn<f32>,
+    /// `None` when the input already has the output rate.
+    resampler: Option<Box<dyn VecResampler<f32>>>,
+    input_rate: u32,
+    output_rate: u32,
     chunk_size: usize,
+    /// Input not yet forming a whole chunk.
     buffer: Vec<f32>,
+    /// One chunk of input and rubato's output for it (one channel each),
+    /// allocated up front and reused.
+    chunk: Vec<Vec<f32>>,
+    output: Vec<Vec<f32>>,
+    /// Leading output frames still to be dropped: the filter's delay.
+    delay_left: usize,
+    /// Input taken and output returned so far.
+    consumed: u64,
+    produced: u64,
 }
 
 impl AudioResampler {
-    pub fn new(input_rate: u32, output_rate: u32, chunk_size: usize) -> Result<Self> {
+    pub fn new(input_rate: u32, output_rate: u32, chunk_size: usize, quality: ResamplerQuality) -> Result<Self> {
         let resample_ratio = output_rate as f64 / input_rate as f64;
         
-        let params = SincInterpolationParameters {
-            sinc_len: 256,
-            f_cutoff: 0.95,
-            interpolation: SincInterpolationType::Linear,
-            oversampling_factor: 256,
-            window: WindowFunction::BlackmanHarris2,
+        let resampler: Option<Box<dyn VecResampler<f32>>> = if input_rate == output_rate {
+            None
+        } else {
+            match quality {
+                ResamplerQuality::Fast => Some(Box::new(FastFixedIn::<f32>::new(
+                    resample_ratio,
+                    1.0,
+                    PolynomialDegree::Septic,
+                    chunk_size,
+                    1, // mono
+                )?)),
+                ResamplerQuality::Balanced => Some(Box::new(FftFixedIn::<f32>::new(
+                    input_rate as usize,
+                    output_rate as usize,
+                    chunk_size,
+                    2,
+                    1, // mono
+                )?)),
+                ResamplerQuality::Best => {
+                    let params = SincInterpolationParameters {
+                        sinc_len: 256,
+                        f_cutoff: 0.95,
+                        interpolation: SincInterpolationType::Linear,
+                        oversampling_factor: 256,
+                        window: WindowFunction::BlackmanHarris2,
+                    };
+                    Some(Box::new(SincFixedIn::<f32>::new(
+                        resample_ratio,
+                        2.0,
+                        params,
+                        chunk_size,
+                        1, // mono
+                    )?))
+                }
+            }
+        };
+        let output = resampler.as_ref().map_or_else(Vec::new, |r| r.output_buffer_allocate(true));
+        // The sinc and polynomial resamplers start with their kernel centred
+        // on the first input sample, so their output is already aligned
+        // (whatever `output_delay` reports); the FFT resampler's is not
+        let delay = match (&resampler, quality) {
+            (Some(r), ResamplerQuality::Balanced) => r.output_delay(),
+            _ => 0,
         };
-        
-        let resampler = SincFixedIn::<f32>::new(
-            resample_ratio,
-            2.0,
-            params,
-            chunk_size,
-            1, // mono
-        )?;
         
         Ok(Self { 
             resampler,
+            input_rate,
+            output_rate,
             chunk_size,
             buffer: Vec::with_capacity(chunk_size * 2),
+            chunk: vec![Vec::with_capacity(chunk_size)],
+            output,
+            delay_left: delay,
+            consumed: 0,
+            produced: 0,
         })
     }
     
-    pub fn process(&mut self, input: &[f32]) -> Result<Vec<f32>> {
-        if input.is_empty() {
-            return Ok(Vec::new());
-        }
+    /// Whether samples are handed through unchanged (equal rates).
+    pub fn is_passthrough(&self) -> bool {
+        self.resampler.is_none()
+    }
+    
+    /// Resample `input`, appending the result to `out`.
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) -> Result<()> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            out.extend_from_slice(input);
+            return Ok(());
+        };
         
         // Add incoming samples to buffer
         self.buffer.extend_from_slice(input);
-        
-        let mut output = Vec::new();
+        self.consumed += input.len() as u64;
         
         // Process complete chunks
-        while self.buffer.len() >= self.chunk_size {
-            // Take exactly chunk_size samples
-            let chunk: Vec<f32> = self.buffer.drain(..self.chunk_size).collect();
-            
-            // Process with Rubato
-            let input_frames = vec![chunk];
-            let output_frames = self.resampler.process(&input_frames, None)?;
+        let mut used = 0;
+        while self.buffer.len() - used >= self.chunk_size {
+            let chunk = &mut self.chunk[0];
+            chunk.clear();
+            chunk.extend_from_slice(&self.buffer[used..used + self.chunk_size]);
+            used += self.chunk_size;
             
-            // Collect output
-            output.extend_from_slice(&output_frames[0]);
+            let (_, frames) = resampler.process_into_buffer(&self.chunk, &mut self.output, None)?;
+            // The first frames out are the filter warming up, not the input
+            let skip = self.delay_left.min(frames);
+            self.delay_left -= skip;
+            out.extend_from_slice(&self.output[0][skip..frames]);
+            self.produced += (frames - skip) as u64;
         }
+        self.buffer.drain(..used);
         
-        Ok(output)
+        Ok(())
     }
     
-    pub fn flush(&mut self) -> Result<Vec<f32>> {
-        // Process any remaining samples by padding to chunk_size
-        if self.buffer.is_empty() {
-            return Ok(Vec::new());
-        }
+    /// End the stream: push the buffered input and the filter's delayed
+    /// tail through with silence, appending exactly the output still owed
+    /// for the input taken so far to `out`.
+    pub fn flush(&mut self, out: &mut Vec<f32>) -> Result<()> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            return Ok(());
+        };
         
-        let remaining = self.buffer.len();
-        if remaining > 0 {
+        let rate_in = self.input_rate as u64;
+        let expected = (self.consumed * self.output_rate as u64 + rate_in / 2) / rate_in;
+        while self.produced < expected {
+            let chunk = &mut self.chunk[0];
+            chunk.clear();
+            chunk.extend_from_slice(&self.buffer);
             // Pad with zeros to reach chunk_size
-            self.buffer.resize(self.chunk_size, 0.0);
-            
-            let chunk = self.buffer.clone();
+            chunk.resize(self.chunk_size, 0.0);
             self.buffer.clear();
             
-            let input_frames = vec![chunk];
-            let output_frames = self.resampler.process(&input_frames, None)?;
-            
-            // Only return the portion corresponding to actual samples
-            let output_len = (remaining as f64 * self.resampler.output_frames_next() as f64 / self.chunk_size as f64) as usize;
-            Ok(output_frames[0][..output_len.min(output_frames[0].len())].to_vec())
+            let (_, frames) = resampler.process_into_buffer(&self.chunk, &mut self.output, None)?;
+            if frames == 0 {
+                break;
+            }
+            let skip = self.delay_left.min(frames);
+            self.delay_left -= skip;
+            let take = (frames - skip).min((expected - self.produced) as usize);
+            out.extend_from_slice(&self.output[0][skip..skip + take]);
+            self.produced += take as u64;
+        }
+        self.buffer.clear();
+        
+        Ok(())
+    }
+}
+
+/// Mixes interleaved device frames down to mono with one weight per channel.
+///
+/// Built from the input's `input_channels` / `channel_weights`; without
+/// either it averages every channel.  Capture batches are not guaranteed to
+/// end on a frame boundary, so a trailing partial frame is kept in `carry`
+/// and prepended to the next batch.
+pub struct ChannelMixer {
+    weights: Vec<f32>,
+    carry: Vec<f32>,
+}
+
+impl ChannelMixer {
+    pub fn new(channels: usize, processing: &InputProcessing) -> Result<Self> {
+        let channels = channels.max(1);
+        let mut weights = vec![0.0f32; channels];
+
+        if let Some(selected) = &processing.input_channels {
+            for &ch in selected {
+                if ch >= channels {
+                    anyhow::bail!(
+                        "input_channels contains channel {} but the device only has {} (0-{})",
+                        ch, channels, channels - 1
+                    );
+                }
+                weights[ch] += 1.0 / selected.len() as f32;
+            }
+        } else if let Some(custom) = &processing.channel_weights {
+            if custom.len() > channels {
+                anyhow::bail!(
+                    "channel_weights has {} entries but the device only has {} channels",
+                    custom.len(), channels
+                );
+            }
+            // Channels without a weight are dropped
+            weights[..custom.len()].copy_from_slice(custom);
         } else {
-            Ok(Vec::new())
+            weights.fill(1.0 / channels as f32);
+        }
+
+        Ok(Self { weights, carry: Vec::with_capacity(channels) })
+    }
+
+    /// Mix `samples` down, appending one mono sample per whole frame to `out`.
+    pub fn mix(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        let channels = self.weights.len();
+        if channels == 1 {
+            out.extend(samples.iter().map(|&s| s * self.weights[0]));
+            return;
+        }
+
+        // Complete the frame left over from the previous batch first
+        let mut rest = samples;
+        if !self.carry.is_empty() {
+            let missing = (channels - self.carry.len()).min(rest.len());
+            self.carry.extend_from_slice(&rest[..missing]);
+            rest = &rest[missing..];
+            if self.carry.len() < channels {
+                return;
+            }
+            out.push(self.mix_frame(&self.carry));
+            self.carry.clear();
+        }
+
+        let whole = rest.len() - rest.len() % channels;
+        out.extend(rest[..whole].chunks_exact(channels).map(|frame| self.mix_frame(frame)));
+        self.carry.extend_from_slice(&rest[whole..]);
+    }
+
+    fn mix_frame(&self, frame: &[f32]) -> f32 {
+        frame.iter().zip(&self.weights).map(|(s, w)| s * w).sum()
+    }
+}
+
+// ── Automatic gain control ────────────────────────────────────────────────────
+
+/// Level is measured per block of this length.
+const AGC_BLOCK_MS: u32 = 20;
+/// Time constant of the short-term RMS the gain follows.
+const AGC_LEVEL_SECS: f32 = 0.3;
+/// Fastest allowed gain reduction (loud input should be tamed quickly).
+const AGC_ATTACK_DB_PER_SEC: f32 = 12.0;
+/// Fastest allowed gain increase (slow, so pauses don't pump up the noise).
+const AGC_RELEASE_DB_PER_SEC: f32 = 3.0;
+/// Blocks quieter than this (before gain) are treated as silence and leave
+/// the gain where it is.
+const AGC_SILENCE_RMS: f32 = 0.001;
+const AGC_MIN_GAIN: f32 = 0.1;
+/// Applied gain is logged when it has moved this much since the last log line.
+const AGC_LOG_STEP_DB: f32 = 1.0;
+
+/// Adaptive gain (`gain_mode = "auto"`) steering the short-term RMS towards
+/// the same `TARGET_RMS` the audio analyzer recommends gains for.
+///
+/// The gain is recomputed once per block and ramped linearly across the next
+/// one, so changes never step audibly.
+pub struct AutoGain {
+    gain: f32,
+    /// Per-sample increment of the current ramp and the gain it ends at.
+    gain_step: f32,
+    target_gain: f32,
+    block_len: usize,
+    block_fill: usize,
+    block_sum: f32,
+    /// Smoothed mean square of the input, once there has been any signal.
+    level: Option<f32>,
+    level_alpha: f32,
+    max_step_up_db: f32,
+    max_step_down_db: f32,
+    logged_gain: f32,
+}
+
+impl AutoGain {
+    pub fn new(sample_rate: u32, initial_gain: f32) -> Self {
+        let block_len = (sample_rate * AGC_BLOCK_MS / 1000).max(1) as usize;
+        let block_secs = block_len as f32 / sample_rate as f32;
+        Self {
+            gain: initial_gain,
+            gain_step: 0.0,
+            target_gain: initial_gain,
+            block_len,
+            block_fill: 0,
+            block_sum: 0.0,
+            level: None,
+            level_alpha: 1.0 - (-block_secs / AGC_LEVEL_SECS).exp(),
+            max_step_up_db: AGC_RELEASE_DB_PER_SEC * block_secs,
+            max_step_down_db: AGC_ATTACK_DB_PER_SEC * block_secs,
+            logged_gain: initial_gain,
+        }
+    }
+
+    /// The gain currently applied.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Apply the gain to `samples` in place.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            let s = *sample;
+            *sample = s * self.gain;
+            self.gain += self.gain_step;
+            self.block_sum += s * s;
+            self.block_fill += 1;
+            if self.block_fill == self.block_len {
+                self.update();
+            }
+        }
+    }
+
+    fn update(&mut self) {
+        let block_ms = self.block_sum / self.block_len as f32;
+        self.block_sum = 0.0;
+        self.block_fill = 0;
+
+        // Land exactly on the end of the previous ramp
+        self.gain = self.target_gain;
+        self.gain_step = 0.0;
+
+        if block_ms.sqrt() < AGC_SILENCE_RMS {
+            return;
+        }
+        let level = match self.level {
+            Some(level) => level + self.level_alpha * (block_ms - level),
+            None => block_ms,
+        };
+        self.level = Some(level);
+
+        let desired = (TARGET_RMS / level.sqrt()).clamp(AGC_MIN_GAIN, MAX_GAIN);
+        let change_db = (20.0 * (desired / self.gain).log10())
+            .clamp(-self.max_step_down_db, self.max_step_up_db);
+        self.target_gain = self.gain * 10f32.powf(change_db / 20.0);
+        self.gain_step = (self.target_gain - self.gain) / self.block_len as f32;
+
+        if (20.0 * (self.target_gain / self.logged_gain).log10()).abs() >= AGC_LOG_STEP_DB {
+            log::info!("AGC: gain {:.2}x", self.target_gain);
+            self.logged_gain = self.target_gain;
         }
     }
 }
 
+/// Summarize how hard the limiter had to work, in the style of the audio analysis.
+fn report_limiting(limiter: &SoftLimiter) {
+    let (limited, processed) = limiter.limited();
+    if processed == 0 {
+        return;
+    }
+    let limited_pct = limited as f64 / processed as f64 * 100.0;
+    log::info!("=== LIMITER ===");
+    log::info!("  Limited samples:  {} of {} ({:.2}%)", limited, processed, limited_pct);
+    log::info!("  Peak after gain:  {:.2} ({:+.1} dBFS)", limiter.input_peak(), 20.0 * limiter.input_peak().max(1e-6).log10());
+    log::info!("===============");
+    if limited_pct > 1.0 {
+        log::warn!("   {:.1}% of samples needed limiting; consider lowering audio_gain", limited_pct);
+    }
+}
+
+/// What the resampler needs to know about the track it processes.
+pub struct TrackInfo {
+    pub format: SourceFormat,
+    /// Shown in front of the track's level meter.
+    pub label: Option<String>,
+    /// Channel mix and DSP chain for this input.
+    pub processing: InputProcessing,
+    /// Where to write the VAD speech segments, when VAD is enabled.
+    pub speech_segments_path: Option<PathBuf>,
+    /// Where to write the level statistics of what went to the WAV.
+    pub audio_stats_path: Option<PathBuf>,
+}
+
+/// Most raw samples taken from the capture queue at once.
+const MAX_BATCH: usize = 4096;
+/// Rubato chunk size (1024 samples at 48kHz = ~21ms)
+const CHUNK_SIZE: usize = 1024;
+
+/// Everything between a track's raw capture queue and its two output
+/// queues: mix → DSP → gain → meter → limiter → resample → noise
+/// suppression → VAD gate.
+///
+/// Each stage works in place or appends into one of the buffers kept here,
+/// so once they have grown to the batch size a batch allocates nothing.
+/// The exceptions are rate-limited: log lines, and redrawing the level
+/// meter (ten times a second while it is shown).
+pub struct TrackProcessor {
+    label: Option<String>,
+    gain: f32,
+    mixer: ChannelMixer,
+    dsp: DspChain,
+    auto_gain: Option<AutoGain>,
+    level_meter: Option<LevelMeter>,
+    limiter: SoftLimiter,
+    resampler: AudioResampler,
+    noise_target: Option<NoiseTarget>,
+    suppressor: Option<NoiseSuppressor>,
+    gate: Option<VoiceActivityGate>,
+    drop_silence: bool,
+    /// Level statistics of the input after gain, collected ahead of the
+    /// limiter so they still show clipping
+    analyzer: AudioAnalyzer,
+    bus: Arc<AudioBus>,
+    // Reused from batch to batch
+    mono: Vec<f32>,
+    resampled: Vec<f32>,
+    cleaned: Vec<f32>,
+    speech: Vec<f32>,
+}
+
+impl TrackProcessor {
+    pub fn new(
+        input_format: SourceFormat,
+        label: Option<String>,
+        processing: &InputProcessing,
+        config: &Config,
+        bus: Arc<AudioBus>,
+    ) -> Result<Self> {
+        let input_rate = input_format.sample_rate;
+        let channels = input_format.channels as usize;
+        let output_rate = config.sample_rate;
+        let gain = config.audio_gain;
+        
+        let mixer = ChannelMixer::new(channels, processing).context("Failed to set up channel mix")?;
+        
+        let quality = ResamplerQuality::from_config(config);
+        let resampler = AudioResampler::new(input_rate, output_rate, CHUNK_SIZE, quality)
+            .context("Failed to create resampler")?;
+        
+        if resampler.is_passthrough() {
+            log::info!("Input already at {} Hz ({} ch), no resampling needed (gain: {}x)",
+                       input_rate, channels, gain);
+        } else {
+            log::info!("Resampling from {} Hz ({} ch) to {} Hz ({:?} quality, chunk size: {} samples, gain: {}x)", 
+                       input_rate, channels, output_rate, quality, CHUNK_SIZE, gain);
+        }
+        
+        // With VAD on, the recognizer only gets speech (and optionally the WAV too)
+        let gate = config.vad_enabled
+            .then(|| VoiceActivityGate::new(output_rate, VadSettings::from_config(config)));
+        
+        // Noise suppression runs before the split, feeding whichever outputs asked for it
+        let noise_target = NoiseTarget::from_config(config);
+        let suppressor = NoiseSuppressor::from_config(config);
+        if let Some(target) = noise_target {
+            log::info!(
+                "Noise suppression on for {:?} (profile: {}, up to {} dB)",
+                target, config.noise_learn, config.noise_reduction_db
+            );
+        }
+        
+        let auto_gain = (config.gain_mode == "auto").then(|| {
+            log::info!("Automatic gain control on (target RMS {}, starting at {}x)", TARGET_RMS, gain);
+            AutoGain::new(input_rate, gain)
+        });
+        
+        let dsp = DspChain::new(&processing.dsp, input_rate).context("Failed to set up DSP chain")?;
+        if !dsp.is_empty() {
+            log::info!("DSP chain: {} stage(s)", processing.dsp.len());
+        }
+        
+        // Room for a full batch (plus a chunk held back) at the output rate
+        let ratio = (output_rate as f64 / input_rate as f64).max(1.0);
+        let out_capacity = ((MAX_BATCH + CHUNK_SIZE) as f64 * ratio).ceil() as usize * 2;
+        
+        Ok(Self {
+            label,
+            gain,
+            mixer,
+            dsp,
+            auto_gain,
+            level_meter: config.level_meter.then(|| LevelMeter::new(input_rate)),
+            limiter: SoftLimiter::new(input_rate, config.limiter_threshold_db, config.limiter_release_ms),
+            resampler,
+            noise_target,
+            suppressor,
+            gate,
+            drop_silence: config.vad_drop_silence,
+            analyzer: AudioAnalyzer::new(input_rate),
+            bus,
+            mono: Vec::with_capacity(MAX_BATCH * 2),
+            resampled: Vec::with_capacity(out_capacity),
+            cleaned: Vec::with_capacity(out_capacity),
+            speech: Vec::with_capacity(out_capacity),
+        })
+    }
+    
+    /// Process one batch of raw interleaved samples.
+    pub fn process(&mut self, samples: &[f32]) {
+        self.run(samples, false);
+    }
+    
+    /// `last` flushes the DSP and limiter look-ahead once the input has ended
+    fn run(&mut self, samples: &[f32], last: bool) {
+        // Pick/mix channels before gain so unused inputs don't add noise
+        let mut mono = std::mem::take(&mut self.mono);
+        mono.clear();
+        self.mixer.mix(samples, &mut mono);
+        let mut mono = self.dsp.process(mono);
+        if last {
+            mono.extend(self.dsp.flush());
+        }
+        
+        // Apply gain (amplification): fixed, or adapted to the input level
+        match self.auto_gain.as_mut() {
+            Some(agc) => agc.process(&mut mono),
+            None => mono.iter_mut().for_each(|s| *s *= self.gain),
+        }
+        
+        // Meter what the gain produced, before the limiter hides any clipping
+        if let Some(level) = self.level_meter.as_mut().and_then(|m| m.process(&mono)) {
+            status_line::set_meter(self.label.as_deref(), level);
+        }
+        self.analyzer.push(&mono);
+        
+        // Bring peaks under the threshold smoothly instead of clipping them;
+        // the clamp only guards against rounding overshoot
+        self.limiter.process(&mut mono);
+        if last {
+            self.limiter.flush(&mut mono);
+        }
+        mono.iter_mut().for_each(|s| *s = s.clamp(-1.0, 1.0));
+        
+        // Process samples (will buffer internally until chunk_size is reached)
+        self.resampled.clear();
+        if let Err(e) = self.resampler.process(&mono, &mut self.resampled) {
+            log::error!("Resampling error: {}", e);
+        }
+        self.mono = mono;
+        if !self.resampled.is_empty() {
+            self.emit(false);
+        }
+    }
+    
+    /// Route `resampled` through noise suppression and the VAD gate to the
+    /// bus's recording and recognition taps.  `last` flushes both.
+    fn emit(&mut self, last: bool) {
+        let Self {
+            resampled, cleaned, speech, suppressor, noise_target, gate, drop_silence, bus, ..
+        } = self;
+        
+        let (wav_stream, recognizer_stream): (&[f32], &[f32]) = match (suppressor.as_mut(), *noise_target) {
+            (Some(suppressor), Some(target)) => {
+                cleaned.clear();
+                suppressor.process(resampled, cleaned);
+                if last {
+                    suppressor.flush(cleaned);
+                }
+                match target {
+                    NoiseTarget::Wav => (cleaned, resampled),
+                    NoiseTarget::Recognizer => (resampled, cleaned),
+                    NoiseTarget::Both => (cleaned, cleaned),
+                }
+            }
+            _ => (resampled, resampled),
+        };
+        let (recording, recognition): (&[f32], &[f32]) = match gate.as_mut() {
+            Some(gate) => {
+                speech.clear();
+                gate.process(recognizer_stream, speech);
+                if last {
+                    gate.finish(speech);
+                }
+                if *drop_silence {
+                    (speech, speech)
+                } else {
+                    (wav_stream, speech)
+                }
+            }
+            None => (wav_stream, recognizer_stream),
+        };
+        bus.publish(Tap::Recording, recording);
+        bus.publish(Tap::Recognition, recognition);
+    }
+    
+    /// Flush every stage and close the bus, then log and write
+    /// the track's reports.
+    fn finish(mut self, speech_segments_path: Option<PathBuf>, audio_stats_path: Option<PathBuf>) {
+        self.run(&[], true);
+        
+        report_limiting(&self.limiter);
+        
+        // Flush any remaining buffered samples
+        log::info!("Flushing resampler buffer...");
+        self.resampled.clear();
+        if let Err(e) = self.resampler.flush(&mut self.resampled) {
+            log::error!("Error flushing resampler: {}", e);
+        }
+        self.emit(true);
+        self.bus.close();
+        
+        if let Some(agc) = &self.auto_gain {
+            log::info!("AGC: final gain {:.2}x", agc.gain());
+        }
+        
+        if let Some(suppressor) = &self.suppressor {
+            match suppressor.noise_frames() {
+                0 => log::warn!("Noise suppression: no noise profile was learned, audio passed through unchanged"),
+                frames => log::info!("Noise suppression: profile built from {} frames", frames),
+            }
+        }
+        
+        if let Some(gate) = &self.gate {
+            let (total, skipped) = gate.totals();
+            log::info!(
+                "VAD: {} speech segments, {:.1}s of {:.1}s held back from recognition",
+                gate.segments().len(), skipped, total
+            );
+            if let Some(path) = speech_segments_path {
+                match gate.write_segments(&path) {
+                    Ok(()) => log::info!("Speech segments saved: {}", path.display()),
+                    Err(e) => log::error!("Failed to write speech segments: {}", e),
+                }
+            }
+        }
+        
+        if let Some(path) = audio_stats_path {
+            // Recommend against the gain in effect at the end, which AGC may
+            // have moved away from audio_gain
+            let gain = self.auto_gain.as_ref().map_or(self.gain, AutoGain::gain);
+            match self.analyzer.finish(gain).write_json(&path) {
+                Ok(()) => log::info!("Audio statistics saved: {}", path.display()),
+                Err(e) => log::error!("Failed to write audio statistics: {}", e),
+            }
+        }
+    }
+}
+
+/// Process a track's raw samples until its capture ring is closed and
+/// drained, then flush and close the bus it publishes to.
 pub fn resampler_thread(
-    raw_queue: Arc<BlockingQueue<f32>>,
-    resampled_queue_writer: Arc<BlockingQueue<f32>>,
-    resampled_queue_realtime: Arc<BlockingQueue<f32>>,
+    raw_queue: Arc<CaptureRing>,
+    bus: Arc<AudioBus>,
+    track: TrackInfo,
     config: Arc<Config>,
-    stop_signal: Arc<AtomicBool>,
 ) {
     log::info!("Resampler thread started");
     
-    // Get device sample rate from the first samples (assuming 48000 Hz for now)
-    let input_rate = 48000;
-    let output_rate = config.sample_rate;
-    let gain = config.audio_gain;
-    
-    // Use a reasonable chunk size for Rubato (1024 samples at 48kHz = ~21ms)
-    let chunk_size = 1024;
-    
-    let mut resampler = match AudioResampler::new(input_rate, output_rate, chunk_size) {
-        Ok(r) => r,
+    let TrackInfo { format, label, processing, speech_segments_path, audio_stats_path } = track;
+    let started = raw_queue.consumer().and_then(|raw| {
+        let processor = TrackProcessor::new(format, label, &processing, &config, Arc::clone(&bus))?;
+        Ok((raw, processor))
+    });
+    let (mut raw, mut processor) = match started {
+        Ok(started) => started,
         Err(e) => {
-            log::error!("Failed to create resampler: {}", e);
+            log::error!("{:#}", e);
+            // Let the consumers finish instead of waiting forever
+            bus.close();
             return;
         }
     };
     
-    log::info!("Resampling from {} Hz to {} Hz (chunk size: {} samples, gain: {}x)", 
-               input_rate, output_rate, chunk_size, gain);
-    
-    while !stop_signal.load(Ordering::Relaxed) {
-        if let Some(samples) = raw_queue.try_pop_batch(4096) {
-            // Convert stereo to mono if needed (average channels)
-            let mono_samples: Vec<f32> = if samples.len() % 2 == 0 {
-                samples.chunks(2)
-                    .map(|chunk| (chunk[0] + chunk.get(1).unwrap_or(&0.0)) / 2.0)
-                    .collect()
-            } else {
-                samples
-            };
-            
-            // Apply gain (amplification)
-            let amplified: Vec<f32> = mono_samples.iter()
-                .map(|&s| (s * gain).clamp(-1.0, 1.0))  // Apply gain and clamp to prevent clipping
-                .collect();
-            
-            // Process samples (will buffer internally until chunk_size is reached)
-            match resampler.process(&amplified) {
-                Ok(resampled) => {
-                    if !resampled.is_empty() {
-                        let resampled_clone = resampled.clone();
-                        if !resampled_queue_writer.push(resampled) {
-                            log::warn!("Resampler: Failed to push to resampled writer queue");
-                        }
-                        if !resampled_queue_realtime.push(resampled_clone) {
-                            log::warn!("Resampler: Failed to push to resampled realtime queue");
-                        }
+    let mut batch = Vec::with_capacity(MAX_BATCH);
+    let mut silence = Vec::new();
+    loop {
+        match raw.pop_into_timeout(&mut batch, MAX_BATCH, Duration::from_millis(500)) {
+            Ok(gap) => {
+                // Capture overruns go through the chain as silence to keep the timeline
+                if gap > 0 {
+                    silence.resize(gap.min(MAX_BATCH), 0.0);
+                    for start in (0..gap).step_by(MAX_BATCH) {
+                        processor.process(&silence[..(gap - start).min(MAX_BATCH)]);
                     }
                 }
-                Err(e) => {
-                    log::error!("Resampling error: {}", e);
-                }
+                processor.process(&batch);
             }
-        } else {
-            std::thread::sleep(Duration::from_millis(10));
+            Err(PopError::Timeout) => continue,
+            Err(PopError::Closed) => break,
         }
     }
+    processor.finish(speech_segments_path, audio_stats_path);
     
-    // Drain remaining samples in raw_queue
-    while let Some(samples) = raw_queue.try_pop_batch(4096) {
-        // Convert stereo to mono if needed (average channels)
-        let mono_samples: Vec<f32> = if samples.len() % 2 == 0 {
-            samples.chunks(2)
-                .map(|chunk| (chunk[0] + chunk.get(1).unwrap_or(&0.0)) / 2.0)
-                .collect()
-        } else {
-            samples
-        };
-        
-        // Apply gain (amplification)
-        let amplified: Vec<f32> = mono_samples.iter()
-            .map(|&s| (s * gain).clamp(-1.0, 1.0))  // Apply gain and clamp to prevent clipping
+    log::info!("Resampler thread finished");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::{BlockingQueue, OverflowPolicy};
+    use crate::config::fixture::parse_with;
+
+    fn test_config() -> Arc<Config> {
+        Arc::new(parse_with(""))
+    }
+
+    fn format(channels: u16, sample_rate: u32) -> SourceFormat {
+        SourceFormat { sample_rate, channels }
+    }
+
+    fn sine(freq: f32, rate: u32, seconds: f32, amplitude: f32) -> Vec<f32> {
+        let n = (rate as f32 * seconds) as usize;
+        (0..n)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * freq * i as f32 / rate as f32).sin())
+            .collect()
+    }
+
+    fn interleave(mono: &[f32], channels: usize) -> Vec<f32> {
+        mono.iter().flat_map(|&s| std::iter::repeat_n(s, channels)).collect()
+    }
+
+    /// Estimate the frequency of a sine from its rising zero crossings,
+    /// ignoring the resampler's start-up and flush edges.
+    fn estimate_frequency(samples: &[f32], rate: u32) -> f32 {
+        let skip = samples.len() / 10;
+        let body = &samples[skip..samples.len() - skip];
+        let crossings: Vec<usize> = body.windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0)
+            .map(|(i, _)| i)
             .collect();
-        
-        // Process samples (will buffer internally until chunk_size is reached)
-        match resampler.process(&amplified) {
-            Ok(resampled) => {
-                if !resampled.is_empty() {
-                    let resampled_clone = resampled.clone();
-                    if !resampled_queue_writer.push(resampled) {
-                        log::warn!("Resampler: Failed to push to resampled writer queue");
-                    }
-                    if !resampled_queue_realtime.push(resampled_clone) {
-                        log::warn!("Resampler: Failed to push to resampled realtime queue");
-                    }
-                }
+        let periods = (crossings.len() - 1) as f32;
+        let span = (crossings[crossings.len() - 1] - crossings[0]) as f32;
+        periods * rate as f32 / span
+    }
+
+    fn subscribe(bus: &AudioBus, name: &str, tap: Tap) -> Arc<BlockingQueue<f32>> {
+        bus.subscribe(name, tap, 1 << 22, OverflowPolicy::DropNewest)
+    }
+
+    /// Push `interleaved` through `resampler_thread` and collect the recording tap.
+    fn run_pipeline(interleaved: Vec<f32>, input_format: SourceFormat) -> Vec<f32> {
+        let raw = CaptureRing::new(interleaved.len() + 1);
+        let bus = AudioBus::new();
+        let writer = subscribe(&bus, "wav", Tap::Recording);
+        let realtime = subscribe(&bus, "realtime", Tap::Recognition);
+        let mut producer = raw.producer().unwrap();
+        for chunk in interleaved.chunks(441) {
+            assert!(producer.push_slice(chunk));
+        }
+
+        // Already closed: the thread drains the ring, flushes and returns
+        drop(producer);
+        raw.close();
+        resampler_thread(
+            Arc::clone(&raw),
+            Arc::clone(&bus),
+            TrackInfo {
+                format: input_format,
+                label: None,
+                processing: InputProcessing::default(),
+                speech_segments_path: None,
+                audio_stats_path: None,
+            },
+            test_config(),
+        );
+
+        let mut out = Vec::new();
+        while let Some(batch) = writer.try_pop_batch(usize::MAX) {
+            out.extend(batch);
+        }
+        let mut rt = Vec::new();
+        while let Some(batch) = realtime.try_pop_batch(usize::MAX) {
+            rt.extend(batch);
+        }
+        assert_eq!(out, rt, "without VAD or noise suppression both taps carry the same audio");
+        // Both outputs are closed once the flushed tail is in them
+        assert_eq!(writer.pop_batch_timeout(1, Duration::ZERO), Err(PopError::Closed));
+        assert_eq!(realtime.pop_batch_timeout(1, Duration::ZERO), Err(PopError::Closed));
+        out
+    }
+
+    fn mix(mixer: &mut ChannelMixer, samples: &[f32]) -> Vec<f32> {
+        let mut out = Vec::new();
+        mixer.mix(samples, &mut out);
+        out
+    }
+
+    #[test]
+    fn default_mix_uses_true_channel_count() {
+        // Mono input must pass through untouched, not be averaged in pairs
+        let mut mono = ChannelMixer::new(1, &parse_with("").processing).unwrap();
+        assert_eq!(mix(&mut mono, &[0.1, 0.2, 0.3, 0.4]), vec![0.1, 0.2, 0.3, 0.4]);
+
+        // Three channels, batch split mid-frame
+        let mut mixer = ChannelMixer::new(3, &parse_with("").processing).unwrap();
+        let out = mix(&mut mixer, &[0.3, 0.6, 0.9, 0.0]);
+        assert_eq!(out.len(), 1);
+        assert!((out[0] - 0.6).abs() < 1e-6);
+        let out = mix(&mut mixer, &[0.3, 0.3]);
+        assert_eq!(out.len(), 1);
+        assert!((out[0] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn selected_channel_is_isolated() {
+        let mut mixer = ChannelMixer::new(4, &parse_with("input_channels = [2]").processing).unwrap();
+        let out = mix(&mut mixer, &[0.9, -0.9, 0.25, 0.9, 0.1, 0.1, -0.5, 0.1]);
+        assert_eq!(out, vec![0.25, -0.5]);
+
+        let mut pair = ChannelMixer::new(4, &parse_with("input_channels = [0, 3]").processing).unwrap();
+        assert_eq!(mix(&mut pair, &[0.2, 0.9, 0.9, 0.4]), vec![0.3]);
+    }
+
+    #[test]
+    fn weighted_mix_applies_weights_and_drops_unlisted_channels() {
+        let mut mixer = ChannelMixer::new(3, &parse_with("channel_weights = [0.5, 2.0]").processing).unwrap();
+        let out = mix(&mut mixer, &[0.4, 0.1, 1.0]);
+        assert!((out[0] - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn channel_selection_out_of_range_errors() {
+        assert!(ChannelMixer::new(2, &parse_with("input_channels = [2]").processing).is_err());
+        assert!(ChannelMixer::new(2, &parse_with("channel_weights = [1.0, 1.0, 1.0]").processing).is_err());
+    }
+
+    #[test]
+    fn resamples_44_1_khz_stereo_without_pitch_shift() {
+        let mono = sine(1000.0, 44100, 1.0, 0.5);
+        let out = run_pipeline(interleave(&mono, 2), format(2, 44100));
+        let expected = 16000;
+        assert!((out.len() as i64 - expected).unsigned_abs() < 1024, "got {} samples", out.len());
+        let freq = estimate_frequency(&out, 16000);
+        assert!((freq - 1000.0).abs() < 5.0, "estimated {} Hz", freq);
+    }
+
+    #[test]
+    fn resamples_22_05_khz_stereo_without_pitch_shift() {
+        let mono = sine(440.0, 22050, 1.0, 0.5);
+        let out = run_pipeline(interleave(&mono, 2), format(2, 22050));
+        assert!((out.len() as i64 - 16000).unsigned_abs() < 1024, "got {} samples", out.len());
+        let freq = estimate_frequency(&out, 16000);
+        assert!((freq - 440.0).abs() < 3.0, "estimated {} Hz", freq);
+    }
+
+    #[test]
+    fn mono_device_keeps_full_duration() {
+        // With the old even-length heuristic this would have been halved
+        let mono = sine(1000.0, 48000, 1.0, 0.5);
+        let out = run_pipeline(mono, format(1, 48000));
+        assert!((out.len() as i64 - 16000).unsigned_abs() < 1024, "got {} samples", out.len());
+        let freq = estimate_frequency(&out, 16000);
+        assert!((freq - 1000.0).abs() < 5.0, "estimated {} Hz", freq);
+    }
+
+    #[test]
+    fn equal_rates_pass_samples_through() {
+        let mono = sine(1000.0, 16000, 1.0, 0.5);
+        let mut resampler = AudioResampler::new(16000, 16000, 1024, ResamplerQuality::Best).unwrap();
+        assert!(resampler.is_passthrough());
+        let mut out = Vec::new();
+        resampler.process(&mono, &mut out).unwrap();
+        resampler.flush(&mut out).unwrap();
+        assert_eq!(out, mono);
+
+        let out = run_pipeline(mono.clone(), format(1, 16000));
+        assert_eq!(out.len(), mono.len());
+    }
+
+    const PRESETS: [ResamplerQuality; 3] = [ResamplerQuality::Fast, ResamplerQuality::Balanced, ResamplerQuality::Best];
+
+    fn resample_all(input: Vec<f32>, input_rate: u32, quality: ResamplerQuality) -> Vec<f32> {
+        let mut resampler = AudioResampler::new(input_rate, 16000, 1024, quality).unwrap();
+        let mut out = Vec::new();
+        for chunk in input.chunks(441) {
+            resampler.process(chunk, &mut out).unwrap();
+        }
+        resampler.flush(&mut out).unwrap();
+        out
+    }
+
+    /// Least-squares fit of a sine at `freq` to the middle of `samples`:
+    /// returns its amplitude and the ratio of fit to residual (dB).
+    fn fit_tone(samples: &[f32], freq: f32, rate: u32) -> (f32, f32) {
+        let body = &samples[samples.len() / 10..samples.len() * 9 / 10];
+        let w = 2.0 * std::f64::consts::PI * freq as f64 / rate as f64;
+        let (mut ss, mut cc, mut sc, mut ys, mut yc) = (0.0f64, 0.0, 0.0, 0.0, 0.0);
+        for (n, &y) in body.iter().enumerate() {
+            let (s, c) = (w * n as f64).sin_cos();
+            ss += s * s;
+            cc += c * c;
+            sc += s * c;
+            ys += y as f64 * s;
+            yc += y as f64 * c;
+        }
+        let det = ss * cc - sc * sc;
+        let a = (ys * cc - yc * sc) / det;
+        let b = (yc * ss - ys * sc) / det;
+        let (mut fit_power, mut residual_power) = (0.0f64, 0.0f64);
+        for (n, &y) in body.iter().enumerate() {
+            let (s, c) = (w * n as f64).sin_cos();
+            let fit = a * s + b * c;
+            fit_power += fit * fit;
+            residual_power += (y as f64 - fit).powi(2);
+        }
+        ((a * a + b * b).sqrt() as f32, (10.0 * (fit_power / residual_power.max(1e-20)).log10()) as f32)
+    }
+
+    #[test]
+    fn every_preset_keeps_a_tone_sweep_accurate() {
+        // Minimum signal-to-residual ratio across the speech band, per preset
+        let min_snr = |quality| match quality {
+            ResamplerQuality::Fast => 40.0,
+            ResamplerQuality::Balanced => 60.0,
+            ResamplerQuality::Best => 60.0,
+        };
+        for quality in PRESETS {
+            for freq in [100.0, 300.0, 1000.0, 2000.0, 3000.0, 4000.0, 5000.0, 6000.0] {
+                let out = resample_all(sine(freq, 48000, 1.0, 0.5), 48000, quality);
+                assert!(out.len().abs_diff(16000) < 1024, "{:?}: {} samples", quality, out.len());
+                let (amplitude, snr) = fit_tone(&out, freq, 16000);
+                let gain_db = 20.0 * (amplitude / 0.5).log10();
+                assert!(gain_db.abs() < 0.5, "{:?} at {} Hz: {:.2} dB", quality, freq, gain_db);
+                assert!(snr > min_snr(quality), "{:?} at {} Hz: SNR {:.1} dB", quality, freq, snr);
             }
-            Err(e) => {
-                log::error!("Resampling error: {}", e);
+        }
+    }
+
+    #[test]
+    fn capture_overruns_become_silence_of_the_same_length() {
+        // Room for 0.1s only: the second 0.1s overruns the ring
+        let tone = sine(440.0, 44100, 0.1, 0.5);
+        let raw = CaptureRing::new(tone.len());
+        let mut producer = raw.producer().unwrap();
+        assert!(producer.push_slice(&tone));
+        assert!(!producer.push_slice(&tone));
+        drop(producer);
+        raw.close();
+
+        let bus = AudioBus::new();
+        let writer = subscribe(&bus, "wav", Tap::Recording);
+        resampler_thread(
+            Arc::clone(&raw),
+            bus,
+            TrackInfo {
+                format: SourceFormat { sample_rate: 44100, channels: 1 },
+                label: None,
+                processing: InputProcessing::default(),
+                speech_segments_path: None,
+                audio_stats_path: None,
+            },
+            test_config(),
+        );
+        let out = writer.try_pop_batch(usize::MAX).unwrap();
+
+        assert_eq!(out.len(), 3200);
+        assert!(rms(&out[200..1400]) > 0.3);
+        assert!(rms(&out[1800..]) < 1e-3, "the dropped 0.1s must be silent");
+    }
+
+    #[test]
+    fn output_length_matches_input_duration_exactly() {
+        for quality in PRESETS {
+            for (input_rate, seconds) in [(48000, 1.0), (44100, 2.5), (22050, 0.3), (8000, 1.7), (16000, 1.0)] {
+                let input = sine(440.0, input_rate, seconds, 0.5);
+                let out = resample_all(input.clone(), input_rate, quality);
+                let expected = input.len() as f64 * 16000.0 / input_rate as f64;
+                assert!(
+                    (out.len() as f64 - expected).abs() <= 1.0,
+                    "{:?} from {} Hz: {} samples for {} s",
+                    quality, input_rate, out.len(), seconds
+                );
             }
         }
+
+        // Same through the whole pipeline (limiter look-ahead included)
+        let out = run_pipeline(sine(1000.0, 48000, 2.0, 0.5), format(1, 48000));
+        assert!(out.len().abs_diff(32000) <= 1, "got {} samples", out.len());
     }
-    
-    // Flush any remaining buffered samples
-    log::info!("Flushing resampler buffer...");
-    match resampler.flush() {
-        Ok(resampled) => {
-            if !resampled.is_empty() {
-                let resampled_clone = resampled.clone();
-                if !resampled_queue_writer.push(resampled) {
-                    log::warn!("Resampler: Failed to push final samples to writer queue");
-                }
-                if !resampled_queue_realtime.push(resampled_clone) {
-                    log::warn!("Resampler: Failed to push final samples to realtime queue");
-                }
+
+    #[test]
+    fn filter_delay_is_trimmed_from_the_start() {
+        for quality in PRESETS {
+            for input_rate in [48000, 44100] {
+                // A smooth ~1 ms pulse half a second in must come out half a second in
+                let centre = input_rate as f32 / 2.0;
+                let width = input_rate as f32 / 1000.0;
+                let input: Vec<f32> = (0..input_rate)
+                    .map(|n| (-0.5 * ((n as f32 - centre) / width).powi(2)).exp())
+                    .collect();
+                let out = resample_all(input, input_rate, quality);
+                let peak = out
+                    .iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+                    .map(|(i, _)| i)
+                    .unwrap();
+                assert!(peak.abs_diff(8000) <= 1, "{:?} from {} Hz: pulse at {}", quality, input_rate, peak);
             }
         }
-        Err(e) => {
-            log::error!("Error flushing resampler: {}", e);
+    }
+
+    #[test]
+    fn filtered_presets_reject_content_above_nyquist() {
+        // 12 kHz can't be represented at 16 kHz; without filtering it folds to 4 kHz
+        for quality in [ResamplerQuality::Balanced, ResamplerQuality::Best] {
+            let out = resample_all(sine(12000.0, 48000, 1.0, 0.5), 48000, quality);
+            let body = &out[out.len() / 10..out.len() * 9 / 10];
+            assert!(rms(body) < 0.005, "{:?}: alias rms {}", quality, rms(body));
         }
     }
-    
-    log::info!("Resampler thread finished");
+
+    /// Throughput of each preset; run with
+    /// `cargo test --release bench_resampler_presets -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_resampler_presets() {
+        let seconds = 60.0;
+        for input_rate in [44100, 48000] {
+            let input = sine(440.0, input_rate, seconds, 0.5);
+            for quality in PRESETS {
+                let started = std::time::Instant::now();
+                let out = resample_all(input.clone(), input_rate, quality);
+                let elapsed = started.elapsed().as_secs_f32();
+                println!(
+                    "{} Hz -> 16 kHz {:>8}: {:>7.1} ms for {} s ({:.0}x real time, {} samples)",
+                    input_rate, format!("{:?}", quality), elapsed * 1000.0, seconds, seconds / elapsed, out.len()
+                );
+            }
+        }
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn auto_gain_brings_quiet_input_to_target_rms() {
+        let mut agc = AutoGain::new(16000, 1.0);
+        let mut out = sine(300.0, 16000, 20.0, 0.01);
+        agc.process(&mut out);
+        let last_second = &out[out.len() - 16000..];
+        assert!((rms(last_second) - TARGET_RMS).abs() < 0.1 * TARGET_RMS, "rms {}", rms(last_second));
+    }
+
+    #[test]
+    fn auto_gain_changes_at_a_limited_rate() {
+        // Rising: at most the release rate
+        let mut agc = AutoGain::new(16000, 1.0);
+        agc.process(&mut sine(300.0, 16000, 1.0, 0.01));
+        let max_up = 10f32.powf(AGC_RELEASE_DB_PER_SEC / 20.0);
+        assert!(agc.gain() > 1.0 && agc.gain() <= max_up * 1.001, "gain {}", agc.gain());
+
+        // Falling: at most the attack rate
+        let mut agc = AutoGain::new(16000, 8.0);
+        agc.process(&mut sine(300.0, 16000, 1.0, 0.9));
+        let max_down = 10f32.powf(-AGC_ATTACK_DB_PER_SEC / 20.0);
+        assert!(agc.gain() < 8.0 && agc.gain() >= 8.0 * max_down * 0.999, "gain {}", agc.gain());
+    }
+
+    #[test]
+    fn auto_gain_holds_during_silence() {
+        let mut agc = AutoGain::new(16000, 2.0);
+        agc.process(&mut sine(300.0, 16000, 2.0, 0.05));
+        // Let the last ramp finish, then nothing may move
+        agc.process(&mut [0.0; 320]);
+        let before = agc.gain();
+        agc.process(&mut vec![0.0; 16000 * 5]);
+        assert_eq!(agc.gain(), before);
+    }
+
+    /// Run `input` (mono, 48 kHz) through a track and return its `_audio.json`.
+    fn audio_stats(config: &Config, input: &[f32]) -> serde_json::Value {
+        let path = std::env::temp_dir()
+            .join(format!("pstt-stats-{}-{}.json", std::process::id(), config.gain_mode));
+        let mut processor = TrackProcessor::new(
+            format(1, 48000), None, &config.processing, config, AudioBus::new(),
+        ).unwrap();
+        processor.process(input);
+        processor.finish(None, Some(path.clone()));
+        let stats = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).ok();
+        stats
+    }
+
+    #[test]
+    fn level_statistics_show_clipping_the_limiter_removes() {
+        let mut config = parse_with("");
+        config.audio_gain = 4.0;
+        let stats = audio_stats(&config, &sine(440.0, 48000, 1.0, 0.5));
+
+        // Measured at the input rate, before the limiter brought the peaks down
+        assert_eq!(stats["sample_rate"], 48000);
+        assert!(stats["peak"].as_f64().unwrap() > 1.9, "{}", stats);
+        assert_eq!(stats["problem"], "clipping");
+        assert!(stats["recommended_gain"].as_f64().unwrap() < 4.0, "{}", stats);
+    }
+
+    #[test]
+    fn level_statistics_refer_to_the_final_automatic_gain() {
+        let config = parse_with(r#"gain_mode = "auto""#);
+        let stats = audio_stats(&config, &sine(300.0, 48000, 5.0, 0.01));
+
+        let gain = stats["audio_gain"].as_f64().unwrap();
+        assert!(gain > 1.5, "{}", stats);
+        // Recommended against the gain that produced the level, not audio_gain
+        let recommended = stats["recommended_gain"].as_f64().unwrap();
+        assert!(recommended >= gain, "{}", stats);
+    }
 }
@@ -30,7 +30,8 @@ use std::sync::mpsc;
 
 use sherpa_rs::sherpa_rs_sys as sys;
 
-use crate::recognition::{RealtimeRecognizer, RecognizedText};
+use crate::recognition::{source_prefix, RealtimeRecognizer, RecognizedText};
+use crate::status_line;
 
 // ── SherpaOnnxRecognizer ──────────────────────────────────────────────────────
 
@@ -39,6 +40,7 @@ pub struct SherpaOnnxRecognizer {
     recognizer: *const sys::SherpaOnnxOnlineRecognizer,
     stream:     *const sys::SherpaOnnxOnlineStream,
     text_sender: mpsc::Sender<RecognizedText>,
+    source: Option<String>,
     sample_rate: i32,
     last_partial: String,
 }
@@ -55,6 +57,7 @@ impl SherpaOnnxRecognizer {
         tokens:      &str,
         sample_rate: u32,
         text_sender: mpsc::Sender<RecognizedText>,
+        source:      Option<String>,
     ) -> Result<Self> {
         // CStrings must live until after SherpaOnnxCreateOnlineRecognizer returns
         let c_encoder        = CString::new(encoder).context("encoder path contains nul")?;
@@ -136,6 +139,7 @@ impl SherpaOnnxRecognizer {
             recognizer,
             stream,
             text_sender,
+            source,
             sample_rate: sample_rate as i32,
             last_partial: String::new(),
         })
@@ -169,17 +173,18 @@ impl SherpaOnnxRecognizer {
     fn emit_and_reset(&mut self, is_final: bool) {
         let text = unsafe { self.get_text() };
         if !text.is_empty() {
-            // \r\x1b[K clears the partial line; \r\n is the correct newline in raw mode
+            // Replaces the partial on the status row with a permanent line
+            let prefix = source_prefix(self.source.as_deref());
             if is_final {
-                print!("\r\x1b[K\u{1f3a4} Final: {}\r\n", text);
+                status_line::print_result(&format!("\u{1f3a4} {}Final: {}", prefix, text));
             } else {
-                print!("\r\x1b[K\u{1f3a4} Recognized: {}\r\n", text);
+                status_line::print_result(&format!("\u{1f3a4} {}Recognized: {}", prefix, text));
             }
-            let _ = std::io::Write::flush(&mut std::io::stdout());
             let _ = self.text_sender.send(RecognizedText {
                 text,
                 timestamp: Local::now(),
                 is_final,
+                source: self.source.clone(),
             });
         }
         unsafe { sys::SherpaOnnxOnlineStreamReset(self.recognizer, self.stream); }
@@ -205,11 +210,8 @@ impl RealtimeRecognizer for SherpaOnnxRecognizer {
             } else {
                 let partial = self.get_text();
                 if !partial.is_empty() && partial != self.last_partial {
-                    // Truncate to ~100 chars so the partial never wraps to a second line
-                    // (\r\x1b[K only clears the current row, so multi-row wraps leave residue)
-                    let end = partial.char_indices().nth(100).map(|(i, _)| i).unwrap_or(partial.len());
-                    print!("\r\x1b[K\u{1f50a} {}", &partial[..end]);
-                    let _ = std::io::Write::flush(&mut std::io::stdout());
+                    // Shares the status row with the level meters, truncated to fit it
+                    status_line::set_partial(&format!("{}{}", source_prefix(self.source.as_deref()), partial));
                     self.last_partial = partial;
                 }
             }
@@ -0,0 +1,539 @@
+//! Audio sources that feed `AudioPipeline::raw_queue`.
+//!
+//! Every source pushes interleaved f32 samples in `-1.0..=1.0` at its own
+//! rate and channel count (see `SourceFormat`); the resampler takes care of
+//! the rest.  Besides the cpal microphone there are sources for replaying a
+//! WAV file, reading raw PCM from stdin and generating synthetic signals,
+//! so the whole pipeline can run without audio hardware.
+
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::audio;
+use crate::buffers::{CaptureProducer, CaptureRing};
+use crate::capture::CaptureHealth;
+
+/// Sample rate and channel count of the interleaved samples a source emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Anything that can feed the raw queue of a recording session.
+pub trait AudioSource: Send {
+    /// Human-readable name for log messages.
+    fn name(&self) -> String;
+
+    /// Rate and channel count of the samples pushed by `open`.
+    fn format(&self) -> SourceFormat;
+
+    /// Start pushing interleaved f32 samples into `raw_queue`, reporting
+    /// liveness through `health`.  Finite sources call
+    /// `CaptureHealth::mark_finished` once all their audio has been pushed.
+    /// Capture stops when the returned guard is dropped.
+    fn open(
+        &mut self,
+        raw_queue: &Arc<CaptureRing>,
+        health: &Arc<CaptureHealth>,
+    ) -> Result<Box<dyn Send>>;
+
+    /// Whether a stalled or failed stream should be reopened.  Only live
+    /// devices come back; files, pipes and generated signals set their own
+    /// pace and end by marking themselves finished.
+    fn recoverable(&self) -> bool {
+        false
+    }
+}
+
+// ── cpal ──────────────────────────────────────────────────────────────────────
+
+/// A cpal input device.  Reopening tries the same device first, then a
+/// device with the same name (it may have been re-enumerated after a
+/// replug), then the host default.  The original rate and channel count are
+/// kept so the resampler downstream stays valid.
+pub struct CpalSource {
+    device: cpal::Device,
+    name: String,
+    stream_config: cpal::SupportedStreamConfig,
+    opened_once: bool,
+}
+
+impl CpalSource {
+    pub fn new(device: cpal::Device) -> Result<Self> {
+        let (name, stream_config) = audio::get_device_info(&device)?;
+        Ok(Self { device, name, stream_config, opened_once: false })
+    }
+
+    fn open_device(
+        &self,
+        device: &cpal::Device,
+        raw_queue: &Arc<CaptureRing>,
+        health: &Arc<CaptureHealth>,
+    ) -> Result<cpal::Stream> {
+        use cpal::traits::{DeviceTrait, StreamTrait};
+
+        // Keep rate and channels; the sample format may differ on another device
+        let sample_format = device.default_input_config()
+            .map(|c| c.sample_format())
+            .unwrap_or(self.stream_config.sample_format());
+        let stream_config = cpal::SupportedStreamConfig::new(
+            self.stream_config.channels(),
+            self.stream_config.sample_rate(),
+            *self.stream_config.buffer_size(),
+            sample_format,
+        );
+        let stream = audio::build_capture_stream(device, &stream_config, raw_queue, Arc::clone(health))?;
+        stream.play()?;
+        Ok(stream)
+    }
+}
+
+impl AudioSource for CpalSource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn format(&self) -> SourceFormat {
+        SourceFormat {
+            sample_rate: self.stream_config.sample_rate(),
+            channels: self.stream_config.channels(),
+        }
+    }
+
+    fn open(
+        &mut self,
+        raw_queue: &Arc<CaptureRing>,
+        health: &Arc<CaptureHealth>,
+    ) -> Result<Box<dyn Send>> {
+        match self.open_device(&self.device, raw_queue, health) {
+            Ok(stream) => {
+                self.opened_once = true;
+                return Ok(Box::new(stream));
+            }
+            // Only fall back to other devices once a recording is under way
+            Err(e) if !self.opened_once => return Err(e),
+            Err(_) => {}
+        }
+
+        let by_name = audio::list_input_devices()?
+            .into_iter()
+            .find(|(_, name)| *name == self.name)
+            .map(|(i, _)| audio::select_device(i));
+        if let Some(Ok(device)) = by_name {
+            if let Ok(stream) = self.open_device(&device, raw_queue, health) {
+                self.device = device;
+                return Ok(Box::new(stream));
+            }
+        }
+
+        let (device, name) = audio::default_input_device()?;
+        let stream = self.open_device(&device, raw_queue, health)
+            .with_context(|| format!("Failed to open default input device {}", name))?;
+        log::info!("Switched input to default device: {}", name);
+        self.device = device;
+        self.name = name;
+        Ok(Box::new(stream))
+    }
+
+    fn recoverable(&self) -> bool {
+        true
+    }
+}
+
+// ── Producer thread shared by the non-cpal sources ────────────────────────────
+
+/// Stops a producer thread when dropped.
+struct ProducerGuard(Arc<AtomicBool>);
+
+impl Drop for ProducerGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Run `next_chunk` on its own thread, pushing ~10 ms of audio at a time.
+///
+/// `next_chunk(frames)` returns up to `frames` interleaved frames, or `None`
+/// at end of input.  With `realtime` the pushes are paced to the wall clock;
+/// otherwise the thread only waits for room in the queue, so no samples are
+/// dropped when the resampler falls behind.  The thread holds `producer`
+/// until it exits.
+fn spawn_producer<F>(
+    format: SourceFormat,
+    realtime: bool,
+    mut producer: CaptureProducer,
+    health: &Arc<CaptureHealth>,
+    mut next_chunk: F,
+) -> Box<dyn Send>
+where
+    F: FnMut(usize) -> Result<Option<Vec<f32>>> + Send + 'static,
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    let (health, done) = (Arc::clone(health), Arc::clone(&stop));
+    let frames_per_chunk = (format.sample_rate as usize / 100).max(1);
+
+    std::thread::spawn(move || {
+        let started = Instant::now();
+        let mut frames_sent: u64 = 0;
+
+        while !done.load(Ordering::Relaxed) {
+            let chunk = match next_chunk(frames_per_chunk) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => {
+                    log::error!("Audio source error: {}", e);
+                    break;
+                }
+            };
+            if chunk.is_empty() {
+                continue;
+            }
+
+            // Wait for room instead of letting the queue drop the batch
+            while producer.free_space() < chunk.len() {
+                if done.load(Ordering::Relaxed) {
+                    return;
+                }
+                health.mark_callback();
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            frames_sent += (chunk.len() / format.channels.max(1) as usize) as u64;
+            producer.push_slice(&chunk);
+            health.mark_callback();
+
+            if realtime {
+                let due = Duration::from_secs_f64(frames_sent as f64 / format.sample_rate as f64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    std::thread::sleep(wait);
+                }
+            }
+        }
+
+        health.mark_finished();
+    });
+
+    Box::new(ProducerGuard(stop))
+}
+
+// ── WAV file replay ───────────────────────────────────────────────────────────
+
+/// Replays a WAV file, either in real time or as fast as the pipeline allows.
+pub struct WavFileSource {
+    path: PathBuf,
+    format: SourceFormat,
+    realtime: bool,
+}
+
+impl WavFileSource {
+    pub fn new(path: &Path, realtime: bool) -> Result<Self> {
+        let reader = hound::WavReader::open(path)
+            .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
+        let spec = reader.spec();
+        Ok(Self {
+            path: path.to_path_buf(),
+            format: SourceFormat { sample_rate: spec.sample_rate, channels: spec.channels },
+            realtime,
+        })
+    }
+}
+
+impl AudioSource for WavFileSource {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn format(&self) -> SourceFormat {
+        self.format
+    }
+
+    fn open(
+        &mut self,
+        raw_queue: &Arc<CaptureRing>,
+        health: &Arc<CaptureHealth>,
+    ) -> Result<Box<dyn Send>> {
+        let mut reader = hound::WavReader::open(&self.path)
+            .with_context(|| format!("Failed to open WAV file: {}", self.path.display()))?;
+        let spec = reader.spec();
+        let channels = self.format.channels as usize;
+        let scale = 1.0 / (1u64 << (spec.bits_per_sample.max(1) - 1)) as f32;
+
+        Ok(spawn_producer(self.format, self.realtime, raw_queue.producer()?, health, move |frames| {
+            let wanted = frames * channels;
+            let chunk: Vec<f32> = match spec.sample_format {
+                hound::SampleFormat::Float => reader.samples::<f32>()
+                    .take(wanted)
+                    .collect::<Result<_, _>>()?,
+                hound::SampleFormat::Int => reader.samples::<i32>()
+                    .take(wanted)
+                    .map(|s| s.map(|s| s as f32 * scale))
+                    .collect::<Result<_, _>>()?,
+            };
+            Ok((!chunk.is_empty()).then_some(chunk))
+        }))
+    }
+}
+
+// ── Raw PCM from stdin ────────────────────────────────────────────────────────
+
+/// Sample encoding of raw PCM input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PcmEncoding {
+    /// Signed 16-bit little-endian
+    S16le,
+    /// 32-bit float little-endian
+    F32le,
+}
+
+impl PcmEncoding {
+    fn bytes_per_sample(self) -> usize {
+        match self {
+            PcmEncoding::S16le => 2,
+            PcmEncoding::F32le => 4,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            PcmEncoding::S16le => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            PcmEncoding::F32le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+/// Reads raw interleaved PCM from a byte stream (normally stdin) until EOF.
+/// The writer on the other end sets the pace.
+pub struct RawPcmSource {
+    reader: Option<Box<dyn Read + Send>>,
+    format: SourceFormat,
+    encoding: PcmEncoding,
+}
+
+impl RawPcmSource {
+    pub fn new(reader: Box<dyn Read + Send>, format: SourceFormat, encoding: PcmEncoding) -> Self {
+        Self { reader: Some(reader), format, encoding }
+    }
+
+    pub fn stdin(format: SourceFormat, encoding: PcmEncoding) -> Self {
+        Self::new(Box::new(std::io::stdin()), format, encoding)
+    }
+}
+
+impl AudioSource for RawPcmSource {
+    fn name(&self) -> String {
+        format!("raw PCM ({:?}, {} Hz, {} ch)", self.encoding, self.format.sample_rate, self.format.channels)
+    }
+
+    fn format(&self) -> SourceFormat {
+        self.format
+    }
+
+    fn open(
+        &mut self,
+        raw_queue: &Arc<CaptureRing>,
+        health: &Arc<CaptureHealth>,
+    ) -> Result<Box<dyn Send>> {
+        let mut reader = self.reader.take().context("Raw PCM input can only be read once")?;
+        let encoding = self.encoding;
+        let frame_bytes = encoding.bytes_per_sample() * self.format.channels as usize;
+        // Bytes of a partial frame left over from the previous read
+        let mut pending: Vec<u8> = Vec::new();
+
+        Ok(spawn_producer(self.format, false, raw_queue.producer()?, health, move |frames| {
+            let mut buf = vec![0u8; frames * frame_bytes];
+            let n = loop {
+                match reader.read(&mut buf) {
+                    Ok(n) => break n,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            };
+            if n == 0 {
+                return Ok(None);
+            }
+            pending.extend_from_slice(&buf[..n]);
+            let whole = pending.len() - pending.len() % frame_bytes;
+            let chunk = pending[..whole]
+                .chunks_exact(encoding.bytes_per_sample())
+                .map(|b| encoding.decode(b))
+                .collect();
+            pending.drain(..whole);
+            Ok(Some(chunk))
+        }))
+    }
+}
+
+// ── Synthetic signals ─────────────────────────────────────────────────────────
+
+/// Test signal produced by `SyntheticSource`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    Tone { frequency: f32, amplitude: f32 },
+    Noise { amplitude: f32 },
+    Silence,
+}
+
+impl std::str::FromStr for Signal {
+    type Err = anyhow::Error;
+
+    /// Parse `tone:<hz>`, `noise` or `silence`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().split_once(':') {
+            Some(("tone", hz)) => Ok(Signal::Tone {
+                frequency: hz.parse().with_context(|| format!("Invalid tone frequency: {}", hz))?,
+                amplitude: 0.25,
+            }),
+            None if s.trim() == "noise" => Ok(Signal::Noise { amplitude: 0.1 }),
+            None if s.trim() == "silence" => Ok(Signal::Silence),
+            _ => anyhow::bail!("Unknown synthetic signal \"{}\". Use tone:<hz>, noise or silence", s),
+        }
+    }
+}
+
+/// Generates a tone, white noise or silence on every channel.
+pub struct SyntheticSource {
+    signal: Signal,
+    format: SourceFormat,
+    duration: Option<Duration>,
+    realtime: bool,
+}
+
+impl SyntheticSource {
+    /// `duration = None` generates until the session is stopped.
+    pub fn new(signal: Signal, format: SourceFormat, duration: Option<Duration>, realtime: bool) -> Self {
+        Self { signal, format, duration, realtime }
+    }
+}
+
+impl AudioSource for SyntheticSource {
+    fn name(&self) -> String {
+        format!("synthetic {:?}", self.signal)
+    }
+
+    fn format(&self) -> SourceFormat {
+        self.format
+    }
+
+    fn open(
+        &mut self,
+        raw_queue: &Arc<CaptureRing>,
+        health: &Arc<CaptureHealth>,
+    ) -> Result<Box<dyn Send>> {
+        let signal = self.signal;
+        let rate = self.format.sample_rate as f64;
+        let channels = self.format.channels as usize;
+        let total_frames = self.duration.map(|d| (d.as_secs_f64() * rate).round() as u64);
+        let mut frame: u64 = 0;
+        // xorshift32 keeps the noise reproducible without pulling in a RNG crate
+        let mut seed: u32 = 0x9E37_79B9;
+
+        Ok(spawn_producer(self.format, self.realtime, raw_queue.producer()?, health, move |frames| {
+            let remaining = total_frames.map_or(frames as u64, |t| t.saturating_sub(frame));
+            let n = remaining.min(frames as u64) as usize;
+            if n == 0 {
+                return Ok(None);
+            }
+            let mut chunk = Vec::with_capacity(n * channels);
+            for _ in 0..n {
+                let value = match signal {
+                    Signal::Tone { frequency, amplitude } => {
+                        let t = frame as f64 / rate;
+                        amplitude * (2.0 * std::f64::consts::PI * frequency as f64 * t).sin() as f32
+                    }
+                    Signal::Noise { amplitude } => {
+                        seed ^= seed << 13;
+                        seed ^= seed >> 17;
+                        seed ^= seed << 5;
+                        amplitude * (seed as f32 / u32::MAX as f32 * 2.0 - 1.0)
+                    }
+                    Signal::Silence => 0.0,
+                };
+                chunk.extend(std::iter::repeat_n(value, channels));
+                frame += 1;
+            }
+            Ok(Some(chunk))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Open `source`, wait for it to finish and return everything it pushed.
+    fn collect(mut source: impl AudioSource) -> Vec<f32> {
+        let queue = CaptureRing::new(1 << 20);
+        let health = CaptureHealth::new();
+        let _guard = source.open(&queue, &health).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !health.is_finished() {
+            assert!(Instant::now() < deadline, "source never finished");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let mut out = Vec::new();
+        queue.consumer().unwrap().pop_into(&mut out, usize::MAX);
+        out
+    }
+
+    #[test]
+    fn synthetic_tone_has_requested_length_and_channels() {
+        let format = SourceFormat { sample_rate: 8000, channels: 2 };
+        let signal: Signal = "tone:1000".parse().unwrap();
+        let out = collect(SyntheticSource::new(signal, format, Some(Duration::from_millis(250)), false));
+        assert_eq!(out.len(), 2000 * 2);
+        // Both channels carry the same signal
+        assert!(out.chunks(2).all(|f| f[0] == f[1]));
+        let peak = out.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!((peak - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn synthetic_signal_parsing() {
+        assert_eq!("silence".parse::<Signal>().unwrap(), Signal::Silence);
+        assert!(matches!("noise".parse::<Signal>().unwrap(), Signal::Noise { .. }));
+        assert!("tone:abc".parse::<Signal>().is_err());
+        assert!("square:440".parse::<Signal>().is_err());
+    }
+
+    #[test]
+    fn wav_file_replay_normalizes_samples() {
+        let path = std::env::temp_dir().join(format!("pstt-source-test-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..1000 {
+            writer.write_sample(if i % 2 == 0 { 16384i16 } else { -32768 }).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let source = WavFileSource::new(&path, false).unwrap();
+        assert_eq!(source.format(), SourceFormat { sample_rate: 44100, channels: 2 });
+        let out = collect(source);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(out.len(), 1000);
+        assert_eq!(out[0], 0.5);
+        assert_eq!(out[1], -1.0);
+    }
+
+    #[test]
+    fn raw_pcm_decodes_until_eof() {
+        let mut bytes = Vec::new();
+        for s in [0i16, 16384, -16384, i16::MIN] {
+            bytes.extend_from_slice(&s.to_le_bytes());
+        }
+        // Trailing half sample is ignored
+        bytes.push(0x7f);
+        let format = SourceFormat { sample_rate: 16000, channels: 1 };
+        let source = RawPcmSource::new(Box::new(std::io::Cursor::new(bytes)), format, PcmEncoding::S16le);
+        assert_eq!(collect(source), vec![0.0, 0.5, -0.5, -1.0]);
+    }
+}
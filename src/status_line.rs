@@ -0,0 +1,158 @@
+//! The live status row at the bottom of the terminal while recording.
+//!
+//! Level meters (one per track) and the recognizer's partial result share a
+//! single row that is redrawn in place with `\r\x1b[K`.  Everything else
+//! that prints during a recording goes through `print_line`, which clears the
+//! row, prints the line with a raw-mode-safe `\r\n` and redraws the row below.
+//!
+//! Tracks only hand in their latest `Level`; the meters are formatted and
+//! drawn by a thread of their own, so the resampler never builds text.
+
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::meter::{self, Level};
+
+static STATUS: Mutex<StatusLine> = Mutex::new(StatusLine::new());
+
+/// Used when the terminal size can't be queried (e.g. output is piped).
+const FALLBACK_WIDTH: usize = 100;
+/// How often the meters are redrawn, the rate they are updated at.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+struct StatusLine {
+    /// Meters are only drawn between `begin` and `end`.
+    active: bool,
+    /// Counts `begin` calls, so a redraw thread knows when it's outdated
+    session: u64,
+    meters: Vec<(Option<String>, Level)>,
+    partial: Option<String>,
+    /// Whether the cursor row currently shows the status.
+    drawn: bool,
+}
+
+impl StatusLine {
+    const fn new() -> Self {
+        Self { active: false, session: 0, meters: Vec::new(), partial: None, drawn: false }
+    }
+
+    fn redraw(&mut self) {
+        let meters: Vec<String> = if self.active {
+            self.meters.iter().map(|(label, level)| meter::render(level, label.as_deref())).collect()
+        } else {
+            Vec::new()
+        };
+        let meters: Vec<&str> = meters.iter().map(String::as_str).collect();
+        let row = compose(&meters, self.partial.as_deref(), terminal_width());
+        if row.is_empty() && !self.drawn {
+            return;
+        }
+        print!("\r\x1b[K{}", row);
+        let _ = std::io::stdout().flush();
+        self.drawn = !row.is_empty();
+    }
+}
+
+fn terminal_width() -> usize {
+    crossterm::terminal::size().map_or(FALLBACK_WIDTH, |(cols, _)| cols as usize)
+}
+
+/// Join the meters and the partial result into one row that fits `width`.
+///
+/// Emoji take two cells, so a few columns are kept free to avoid wrapping
+/// (`\x1b[K` only clears the current row).
+pub fn compose(meters: &[&str], partial: Option<&str>, width: usize) -> String {
+    let mut parts: Vec<String> = meters.iter().map(|m| m.to_string()).collect();
+    if let Some(partial) = partial.filter(|p| !p.is_empty()) {
+        parts.push(format!("🔊 {}", partial));
+    }
+    parts.join("  ").chars().take(width.saturating_sub(6)).collect()
+}
+
+/// Start showing level meters (called when a recording starts).
+pub fn begin() {
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    status.active = true;
+    status.meters.clear();
+    status.session += 1;
+    let session = status.session;
+    std::thread::spawn(move || loop {
+        std::thread::sleep(REDRAW_INTERVAL);
+        let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+        if !status.active || status.session != session {
+            break;
+        }
+        if !status.meters.is_empty() {
+            status.redraw();
+        }
+    });
+}
+
+/// Stop showing level meters and clear the row.
+pub fn end() {
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    status.active = false;
+    status.meters.clear();
+    status.partial = None;
+    status.redraw();
+}
+
+/// Replace the level shown for the track `label`; it is drawn on the next
+/// redraw.  Only a track's first level allocates.
+pub fn set_meter(label: Option<&str>, level: Level) {
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    if !status.active {
+        return;
+    }
+    match status.meters.iter_mut().find(|(l, _)| l.as_deref() == label) {
+        Some((_, l)) => *l = level,
+        None => status.meters.push((label.map(str::to_string), level)),
+    }
+}
+
+/// Show the recognizer's current partial result next to the meters.
+#[cfg_attr(not(feature = "sherpa-engine"), allow(dead_code))]
+pub fn set_partial(text: &str) {
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    status.partial = Some(text.to_string());
+    status.redraw();
+}
+
+/// Print a permanent line above the status row.
+pub fn print_line(line: &str) {
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    print_above(&mut status, line);
+}
+
+/// Print a finished result, replacing the partial it grew from.
+pub fn print_result(line: &str) {
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    status.partial = None;
+    print_above(&mut status, line);
+}
+
+fn print_above(status: &mut StatusLine, line: &str) {
+    print!("\r\x1b[K{}\r\n", line);
+    let _ = std::io::stdout().flush();
+    status.drawn = false;
+    status.redraw();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meters_and_partial_share_one_row_that_never_wraps() {
+        let row = compose(&["[mic] -20 dB", "[monitor] -35 dB"], Some("hello there"), 80);
+        assert_eq!(row, "[mic] -20 dB  [monitor] -35 dB  🔊 hello there");
+
+        let long = "word ".repeat(50);
+        let row = compose(&["[mic] -20 dB"], Some(&long), 40);
+        assert_eq!(row.chars().count(), 34);
+        assert!(row.starts_with("[mic] -20 dB  🔊 word"));
+
+        assert_eq!(compose(&[], Some(""), 80), "");
+    }
+}
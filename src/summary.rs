@@ -4,6 +4,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use crate::config::Config;
+use crate::recognition::source_prefix;
 
 #[derive(Serialize)]
 struct OllamaGenerateRequest {
@@ -38,11 +39,29 @@ pub fn generate_summary_from_file(
     input_path: &Path,
     output_path: &Path,
 ) -> Result<()> {
-    let input_text = fs::read_to_string(input_path)
-        .with_context(|| format!("Failed to read transcript: {}", input_path.display()))?;
+    generate_summary_from_files(config, &[(None, input_path.to_path_buf())], output_path)
+}
+
+/// Summarize several transcripts as one, e.g. one per recorded track.  A
+/// labeled transcript is introduced by its label.
+pub fn generate_summary_from_files(
+    config: &Config,
+    inputs: &[(Option<String>, PathBuf)],
+    output_path: &Path,
+) -> Result<()> {
+    let mut transcripts = Vec::new();
+    for (label, path) in inputs {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read transcript: {}", path.display()))?;
+        if !text.trim().is_empty() {
+            transcripts.push(format!("{}{}", source_prefix(label.as_deref()), text.trim()));
+        }
+    }
+    let input_text = transcripts.join("\n\n");
 
-    if input_text.trim().is_empty() {
-        log::warn!("Transcript is empty, skipping summary generation: {}", input_path.display());
+    if input_text.is_empty() {
+        let names: Vec<String> = inputs.iter().map(|(_, path)| path.display().to_string()).collect();
+        log::warn!("Transcript is empty, skipping summary generation: {}", names.join(", "));
         return Ok(());
     }
 
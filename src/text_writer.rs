@@ -2,7 +2,7 @@ use std::sync::mpsc;
 use std::fs::File;
 use std::io::{Write, BufWriter};
 use anyhow::Result;
-use crate::recognition::RecognizedText;
+use crate::recognition::{source_prefix, RecognizedText};
 
 pub fn text_writer_thread(
     text_receiver: mpsc::Receiver<RecognizedText>,
@@ -18,11 +18,12 @@ pub fn text_writer_thread(
     let mut line_count = 0;
     
     while let Ok(recognized) = text_receiver.recv() {
-        // Write with timestamp
+        // Write with timestamp, and the input label when there are several
         writeln!(
             writer,
-            "[{}] {}",
+            "[{}] {}{}",
             recognized.timestamp.format("%H:%M:%S"),
+            source_prefix(recognized.source.as_deref()),
             recognized.text
         )?;
         
@@ -0,0 +1,356 @@
+//! Voice activity detection on the resampled stream.
+//!
+//! The gate sits right after the resampler.  It splits the 16 kHz signal
+//! into 20 ms frames, classifies each one as speech or silence, and only
+//! lets speech through — plus a short pre-roll before each onset and up to
+//! `vad_max_silence_ms` after it, so recognizers still see enough trailing
+//! silence to finish their utterances.  Speech regions are collected as
+//! segments with sample offsets and written to a JSON sidecar at the end.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::Path;
+
+use crate::config::Config;
+
+const FRAME_MS: u32 = 20;
+/// Audio kept from before a speech onset so the first syllable isn't cut.
+const PREROLL_MS: u32 = 200;
+
+/// Classifies fixed-size frames as speech or non-speech.
+///
+/// `EnergyDetector` is the built-in implementation; a model-based detector
+/// (e.g. Silero) only needs to implement this trait.
+pub trait SpeechDetector: Send {
+    fn is_speech(&mut self, frame: &[f32]) -> bool;
+}
+
+// ── Energy / zero-crossing detector ──────────────────────────────────────────
+
+/// Frames quieter than this are never speech, whatever the noise floor.
+const ABSOLUTE_FLOOR_DB: f32 = -60.0;
+/// How fast the noise floor creeps up while the signal is above it.
+const FLOOR_RISE_DB_PER_FRAME: f32 = 0.02;
+/// Zero-crossing rate above which a quieter frame still counts as speech
+/// (unvoiced fricatives like "s" and "f" are noisy but low in energy).
+const FRICATIVE_ZCR: f32 = 0.25;
+
+/// Speech when a frame's energy is `margin_db` above a running noise floor.
+pub struct EnergyDetector {
+    margin_db: f32,
+    noise_floor_db: Option<f32>,
+}
+
+impl EnergyDetector {
+    pub fn new(margin_db: f32) -> Self {
+        Self { margin_db, noise_floor_db: None }
+    }
+}
+
+impl SpeechDetector for EnergyDetector {
+    fn is_speech(&mut self, frame: &[f32]) -> bool {
+        if frame.is_empty() {
+            return false;
+        }
+        let mean_square = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+        let energy_db = 10.0 * (mean_square + 1e-12).log10();
+        let crossings = frame.windows(2).filter(|w| (w[0] < 0.0) != (w[1] < 0.0)).count();
+        let zcr = crossings as f32 / frame.len() as f32;
+
+        // Follow drops immediately, rise slowly so speech doesn't lift the floor
+        let floor = self.noise_floor_db.get_or_insert(energy_db);
+        if energy_db < *floor {
+            *floor = energy_db;
+        } else {
+            *floor = (*floor + FLOOR_RISE_DB_PER_FRAME).min(energy_db);
+        }
+        let above = energy_db - *floor;
+
+        energy_db > ABSOLUTE_FLOOR_DB
+            && (above >= self.margin_db || (above >= self.margin_db / 2.0 && zcr >= FRICATIVE_ZCR))
+    }
+}
+
+// ── Gate ─────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct VadSettings {
+    /// Energy above the noise floor (dB) that counts as speech.
+    pub threshold_db: f32,
+    /// Silence inside a segment shorter than this doesn't end it.
+    pub hangover_ms: u32,
+    /// Silence passed on after speech before the gate closes.
+    pub max_silence_ms: u32,
+    /// Whether the WAV also gets the gated stream (affects `wav_start`).
+    pub drop_silence: bool,
+}
+
+impl VadSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            threshold_db: config.vad_threshold_db,
+            hangover_ms: config.vad_hangover_ms,
+            max_silence_ms: config.vad_max_silence_ms,
+            drop_silence: config.vad_drop_silence,
+        }
+    }
+}
+
+/// One region of speech.  Offsets are in samples at the output rate.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpeechSegment {
+    /// First sample of the segment in the recording timeline.
+    pub start: u64,
+    /// One past the last speech sample in the recording timeline.
+    pub end: u64,
+    /// Where the segment starts in the WAV file; equals `start` unless
+    /// silence is dropped from the WAV.
+    pub wav_start: u64,
+}
+
+#[derive(Serialize)]
+struct SegmentsFile<'a> {
+    sample_rate: u32,
+    silence_dropped: bool,
+    segments: &'a [SpeechSegment],
+}
+
+pub struct VoiceActivityGate {
+    detector: Box<dyn SpeechDetector>,
+    settings: VadSettings,
+    sample_rate: u32,
+    frame_len: usize,
+    hangover: usize,
+    max_silence: usize,
+    /// Samples not yet forming a whole frame.
+    pending: Vec<f32>,
+    /// Most recent gated-out audio, replayed at the next speech onset.
+    preroll: VecDeque<f32>,
+    preroll_len: usize,
+    /// Samples classified so far (recording timeline).
+    position: u64,
+    /// Samples let through so far (gated timeline).
+    emitted: u64,
+    /// Silence since the last speech frame; starts "closed".
+    silence_run: usize,
+    last_speech_end: u64,
+    /// `(start, wav_start)` of the segment in progress.
+    open_segment: Option<(u64, u64)>,
+    segments: Vec<SpeechSegment>,
+}
+
+impl VoiceActivityGate {
+    pub fn new(sample_rate: u32, settings: VadSettings) -> Self {
+        Self::with_detector(sample_rate, settings.clone(), Box::new(EnergyDetector::new(settings.threshold_db)))
+    }
+
+    pub fn with_detector(sample_rate: u32, settings: VadSettings, detector: Box<dyn SpeechDetector>) -> Self {
+        let ms = |ms: u32| (sample_rate as u64 * ms as u64 / 1000) as usize;
+        Self {
+            detector,
+            frame_len: ms(FRAME_MS).max(1),
+            hangover: ms(settings.hangover_ms),
+            max_silence: ms(settings.max_silence_ms),
+            preroll_len: ms(PREROLL_MS),
+            settings,
+            sample_rate,
+            pending: Vec::new(),
+            preroll: VecDeque::new(),
+            position: 0,
+            emitted: 0,
+            silence_run: usize::MAX,
+            last_speech_end: 0,
+            open_segment: None,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Classify `samples` and append the part that passes the gate to `out`.
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        // Taken out (and put back) so frames can be borrowed while `self` changes
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(samples);
+        let whole = pending.len() - pending.len() % self.frame_len;
+        for frame in pending[..whole].chunks_exact(self.frame_len) {
+            self.process_frame(frame, out);
+        }
+        pending.drain(..whole);
+        self.pending = pending;
+    }
+
+    /// Classify the trailing partial frame, append what passes to `out` and
+    /// close the last segment.
+    pub fn finish(&mut self, out: &mut Vec<f32>) {
+        let rest = std::mem::take(&mut self.pending);
+        if !rest.is_empty() {
+            self.process_frame(&rest, out);
+        }
+        self.close_segment();
+    }
+
+    pub fn segments(&self) -> &[SpeechSegment] {
+        &self.segments
+    }
+
+    /// Total audio seen and how much of it was held back, in seconds.
+    pub fn totals(&self) -> (f64, f64) {
+        let rate = self.sample_rate as f64;
+        (self.position as f64 / rate, (self.position - self.emitted) as f64 / rate)
+    }
+
+    pub fn write_segments(&self, path: &Path) -> Result<()> {
+        let file = SegmentsFile {
+            sample_rate: self.sample_rate,
+            silence_dropped: self.settings.drop_silence,
+            segments: &self.segments,
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+
+    fn process_frame(&mut self, frame: &[f32], out: &mut Vec<f32>) {
+        let speech = self.detector.is_speech(frame);
+        let frame_start = self.position;
+        self.position += frame.len() as u64;
+
+        if speech {
+            self.silence_run = 0;
+            self.last_speech_end = self.position;
+            if self.open_segment.is_none() {
+                // Pre-roll (if any) lands in the output right before this frame
+                let wav_start = if self.settings.drop_silence {
+                    self.emitted + self.preroll.len() as u64
+                } else {
+                    frame_start
+                };
+                self.open_segment = Some((frame_start, wav_start));
+            }
+        } else {
+            self.silence_run = self.silence_run.saturating_add(frame.len());
+            if self.silence_run >= self.hangover {
+                self.close_segment();
+            }
+        }
+
+        if speech || self.silence_run <= self.max_silence {
+            self.emitted += (self.preroll.len() + frame.len()) as u64;
+            out.extend(self.preroll.drain(..));
+            out.extend_from_slice(frame);
+        } else {
+            self.preroll.extend(frame);
+            let excess = self.preroll.len().saturating_sub(self.preroll_len);
+            self.preroll.drain(..excess);
+        }
+    }
+
+    fn close_segment(&mut self) {
+        if let Some((start, wav_start)) = self.open_segment.take() {
+            self.segments.push(SpeechSegment { start, end: self.last_speech_end, wav_start });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn settings(drop_silence: bool) -> VadSettings {
+        VadSettings { threshold_db: 12.0, hangover_ms: 300, max_silence_ms: 500, drop_silence }
+    }
+
+    /// Deterministic low-level noise so the detector has a floor to track.
+    fn noise(seconds: f32) -> Vec<f32> {
+        let mut state = 0x2545_f491u32;
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * 0.003
+            })
+            .collect()
+    }
+
+    fn tone(seconds: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| 0.3 * (2.0 * std::f32::consts::PI * 300.0 * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    /// 1 s noise, 1 s tone, 2 s noise, 0.5 s tone, 1 s noise.
+    fn bursts() -> Vec<f32> {
+        [noise(1.0), tone(1.0), noise(2.0), tone(0.5), noise(1.0)].concat()
+    }
+
+    fn run(gate: &mut VoiceActivityGate, signal: &[f32]) -> Vec<f32> {
+        let mut out = Vec::new();
+        for chunk in signal.chunks(1000) {
+            gate.process(chunk, &mut out);
+        }
+        gate.finish(&mut out);
+        out
+    }
+
+    fn near(actual: u64, expected: u64) -> bool {
+        let frame = (RATE * FRAME_MS / 1000) as i64;
+        (actual as i64 - expected as i64).abs() <= frame
+    }
+
+    #[test]
+    fn marks_speech_segments_with_sample_offsets() {
+        let mut gate = VoiceActivityGate::new(RATE, settings(false));
+        run(&mut gate, &bursts());
+
+        let segments = gate.segments();
+        assert_eq!(segments.len(), 2, "{:?}", segments);
+        assert!(near(segments[0].start, 16000) && near(segments[0].end, 32000), "{:?}", segments[0]);
+        assert!(near(segments[1].start, 64000) && near(segments[1].end, 72000), "{:?}", segments[1]);
+        assert_eq!(segments[1].wav_start, segments[1].start);
+    }
+
+    #[test]
+    fn gate_skips_long_silence_but_keeps_preroll_and_tail() {
+        let mut gate = VoiceActivityGate::new(RATE, settings(true));
+        let out = run(&mut gate, &bursts());
+
+        // Each burst passes with 200 ms pre-roll and 500 ms of trailing silence
+        let expected = 2 * (3200 + 8000) + 16000 + 8000;
+        assert!((out.len() as i64 - expected as i64).abs() <= 2 * 320, "got {}", out.len());
+        let (total, skipped) = gate.totals();
+        assert!((total - 5.5).abs() < 0.01);
+        assert!((skipped - (5.5 - out.len() as f64 / RATE as f64)).abs() < 1e-6);
+
+        // With silence dropped, the second segment starts after the first one's tail
+        let segments = gate.segments();
+        assert!(near(segments[0].wav_start, 3200), "{:?}", segments[0]);
+        assert!(near(segments[1].wav_start, 3200 + 16000 + 8000 + 3200), "{:?}", segments[1]);
+        let wav_offset = segments[1].wav_start as usize;
+        assert!(out[wav_offset..wav_offset + 320].iter().any(|s| s.abs() > 0.1));
+    }
+
+    #[test]
+    fn digital_silence_is_never_speech() {
+        let mut gate = VoiceActivityGate::new(RATE, settings(false));
+        let out = run(&mut gate, &vec![0.0; RATE as usize * 2]);
+        assert!(out.is_empty());
+        assert!(gate.segments().is_empty());
+    }
+
+    #[test]
+    fn segments_sidecar_is_json() {
+        let mut gate = VoiceActivityGate::new(RATE, settings(false));
+        run(&mut gate, &bursts());
+        let path = std::env::temp_dir().join(format!("pstt-vad-{}.json", std::process::id()));
+        gate.write_segments(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(json["sample_rate"], 16000);
+        assert_eq!(json["silence_dropped"], false);
+        assert_eq!(json["segments"].as_array().unwrap().len(), 2);
+        assert!(json["segments"][0]["end"].as_u64().unwrap() > json["segments"][0]["start"].as_u64().unwrap());
+    }
+}
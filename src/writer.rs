@@ -2,8 +2,7 @@ use hound::{WavWriter, WavSpec};
 use std::path::PathBuf;
 use anyhow::Result;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use crate::buffers::BlockingQueue;
+use crate::buffers::{BlockingQueue, CloseOnDrop, PopError};
 use std::time::Duration;
 
 pub fn build_wav_path(output_dir: &str, base_name: &str) -> PathBuf {
@@ -27,38 +26,47 @@ pub fn create_wav_writer(
     Ok(writer)
 }
 
+/// Write everything from `resampled_queue` to a 16-bit WAV file, until the
+/// queue is closed and drained.  Samples the queue dropped on overflow are
+/// written as silence, so the file keeps its timing.
 pub fn writer_thread(
     resampled_queue: Arc<BlockingQueue<f32>>,
     output_path: PathBuf,
     sample_rate: u32,
-    stop_signal: Arc<AtomicBool>,
 ) -> Result<PathBuf> {
     log::info!("WAV writer thread started");
+    // Stop the bus filling the queue if writing fails
+    let _close = CloseOnDrop(Arc::clone(&resampled_queue));
     
     log::info!("Recording to: {}", output_path.display());
     
     let mut writer = create_wav_writer(&output_path, sample_rate)?;
+    let mut padded: u64 = 0;
+    let mut samples = Vec::with_capacity(1024);
     
-    while !stop_signal.load(Ordering::Relaxed) {
-        // Use try_pop_batch with a timeout to check stop signal periodically
-        if let Some(samples) = resampled_queue.try_pop_batch(1024) {
-            for sample in samples {
-                let sample_i16 = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
-                writer.write_sample(sample_i16)?;
-            }
-        } else {
-            std::thread::sleep(Duration::from_millis(10));
+    loop {
+        let gap = match resampled_queue.pop_into_timeout(&mut samples, 1024, Duration::from_millis(500)) {
+            Ok(gap) => gap,
+            Err(PopError::Timeout) => continue,
+            Err(PopError::Closed) => break,
+        };
+        for _ in 0..gap {
+            writer.write_sample(0i16)?;
         }
-    }
-    
-    // Drain remaining samples
-    while let Some(samples) = resampled_queue.try_pop_batch(1024) {
-        for sample in samples {
+        padded += gap as u64;
+        for &sample in &samples {
             let sample_i16 = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
             writer.write_sample(sample_i16)?;
         }
     }
     
+    if padded > 0 {
+        log::warn!(
+            "WAV writer: {} dropped samples ({:.2}s) written as silence",
+            padded,
+            padded as f64 / sample_rate as f64
+        );
+    }
     writer.finalize()?;
     log::info!("WAV writer thread finished: {}", output_path.display());
     
@@ -0,0 +1,100 @@
+//! The resampler's steady state must not touch the heap.  This lives in its
+//! own test binary because counting needs a global allocator, which would
+//! otherwise be installed for every unit test in the crate.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use pstt::buffers::OverflowPolicy;
+use pstt::bus::{AudioBus, Tap};
+use pstt::config::fixture::parse_with;
+use pstt::resampler::TrackProcessor;
+use pstt::source::SourceFormat;
+use pstt::status_line;
+
+/// Counts the heap allocations of the current thread, so the harness's own
+/// threads don't disturb the count.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+fn stereo_sine(freq: f32, rate: u32, seconds: f32, amplitude: f32) -> Vec<f32> {
+    let n = (rate as f32 * seconds) as usize;
+    (0..n)
+        .map(|i| amplitude * (2.0 * std::f32::consts::PI * freq * i as f32 / rate as f32).sin())
+        .flat_map(|s| [s, s])
+        .collect()
+}
+
+#[test]
+fn steady_state_batches_do_not_allocate() {
+    for quality in ["fast", "balanced", "best"] {
+        // Every stage on, the level meter included
+        let config = parse_with(&format!(r#"
+            resampler_quality = "{}"
+            gain_mode = "auto"
+            level_meter = true
+            noise_suppression = "both"
+            vad_enabled = true
+
+            [[dsp]]
+            type = "high_pass"
+
+            [[dsp]]
+            type = "limiter"
+        "#, quality));
+        let bus = AudioBus::new();
+        let writer = bus.subscribe("wav", Tap::Recording, 1 << 22, OverflowPolicy::DropNewest);
+        let _realtime = bus.subscribe("realtime", Tap::Recognition, 1 << 22, OverflowPolicy::DropNewest);
+        let format = SourceFormat { sample_rate: 48000, channels: 2 };
+        let mut processor = TrackProcessor::new(format, Some("mic".into()), &config.processing, &config, bus)
+            .unwrap();
+
+        // Odd-sized batches that split frames and chunks differently each time
+        let input = stereo_sine(440.0, 48000, 0.5, 0.3);
+        let batches: Vec<&[f32]> = input.chunks(883).collect();
+        // The meter's levels must reach the status line without allocating
+        status_line::begin();
+        // Warm-up lets every buffer reach its working size
+        for _ in 0..3 {
+            batches.iter().for_each(|batch| processor.process(batch));
+        }
+
+        let before = allocations();
+        batches.iter().for_each(|batch| processor.process(batch));
+        let allocated = allocations() - before;
+        status_line::end();
+        assert_eq!(allocated, 0, "{} allocated in the steady state", quality);
+        // (A steady tone soon counts as noise to the VAD, so only the WAV gets it)
+        assert!(writer.try_pop_batch(usize::MAX).is_some());
+    }
+}